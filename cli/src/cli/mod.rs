@@ -1,9 +1,17 @@
-use crate::{config::Config, utils, utxo::SerializedSignedTransaction};
+use crate::config::Config;
 use clap::{Parser, Subcommand};
 use std::path::{Path, PathBuf};
+use utxo_miden::{
+    storage::Store,
+    utils::{self, HexString},
+    utxo::{Batch, SerializedSignedTransaction, SignedTransaction, State},
+};
 
 pub mod no_zk;
 pub mod prove;
+pub mod serve;
+pub mod verify;
+pub mod wallet;
 
 #[derive(Parser)]
 pub struct Cli {
@@ -18,25 +26,164 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Command {
     /// Generate a proof from executing MidenVM.
+    /// The transaction is applied to the latest state in the store unless a root is given.
     Prove {
         #[clap(short, long)]
         tx_path: String,
+        /// Root of a previous state (from the store history) to prove against.
+        #[clap(short, long)]
+        root: Option<HexString>,
+        /// Height to apply the transactions at (the height of the state by default).
+        #[clap(long)]
+        height: Option<u64>,
+    },
+    /// Generate a single proof from executing MidenVM on a batch of transactions.
+    /// The batch is either a JSON file with an array of signed transactions or a directory
+    /// of signed transaction JSON files (applied in the order of their file names).
+    ProveBatch {
+        #[clap(short, long)]
+        batch_path: String,
+        /// Root of a previous state (from the store history) to prove against.
+        #[clap(short, long)]
+        root: Option<HexString>,
+        /// Height to apply the transactions at (the height of the state by default).
+        #[clap(long)]
+        height: Option<u64>,
+    },
+    /// Execute MidenVM on a transaction (or a batch) without generating a proof, to check
+    /// quickly whether it would succeed. The result is compared with the Rust implementation.
+    Run {
+        #[clap(short, long, required_unless_present = "batch_path")]
+        tx_path: Option<String>,
+        #[clap(short, long, conflicts_with = "tx_path")]
+        batch_path: Option<String>,
+        /// Root of a previous state (from the store history) to run against.
+        #[clap(short, long)]
+        root: Option<HexString>,
+        /// Height to apply the transactions at (the height of the state by default).
+        #[clap(long)]
+        height: Option<u64>,
+    },
+    /// Verify the proof written by `Prove` (or `ProveBatch`) for the same transaction (or batch).
+    /// The proof is read from the configured outputs path.
+    Verify {
+        #[clap(short, long, required_unless_present = "batch_path")]
+        tx_path: Option<String>,
+        #[clap(short, long, conflicts_with = "tx_path")]
+        batch_path: Option<String>,
+        /// Root of the state the proof was generated against (the latest state by default).
+        #[clap(short, long)]
+        root: Option<HexString>,
+        /// Height the proof was generated at (the height of the state by default).
+        #[clap(long)]
+        height: Option<u64>,
+    },
+    /// Run a local JSON-RPC node (listening on 127.0.0.1 only) which accepts signed
+    /// transactions, applies them to the state store and serves proofs for them.
+    Serve {
+        #[clap(short, long, default_value_t = 8080)]
+        port: u16,
     },
     #[clap(subcommand)]
     NoZk(no_zk::Command),
+    /// Balances and UTXOs of the owners with a key file, in the latest state.
+    #[clap(subcommand)]
+    Wallet(wallet::Command),
 }
 
 pub fn execute(config: &Config, command: Command) -> anyhow::Result<()> {
     match command {
-        Command::Prove { tx_path } => {
+        Command::Prove {
+            tx_path,
+            root,
+            height,
+        } => {
             let signed_tx: SerializedSignedTransaction =
                 utils::read_json_file(Path::new(&tx_path))?;
-            let output = prove::execute(config, signed_tx.try_into()?)?;
+            let signed_tx = SignedTransaction::try_from(signed_tx)?;
+            let state = load_state(config, root, height)?;
+            let output = prove::execute(config, &state, signed_tx.into())?;
+            output.write_to_file(&config.outputs_path)?;
+            println!("Proof written to {:?}", config.outputs_path);
+        }
+        Command::ProveBatch {
+            batch_path,
+            root,
+            height,
+        } => {
+            let batch = utils::read_batch(Path::new(&batch_path))?;
+            let state = load_state(config, root, height)?;
+            let output = prove::execute(config, &state, batch)?;
             output.write_to_file(&config.outputs_path)?;
             println!("Proof written to {:?}", config.outputs_path);
         }
+        Command::Run {
+            tx_path,
+            batch_path,
+            root,
+            height,
+        } => {
+            let batch = read_batch(tx_path, batch_path)?;
+            let state = load_state(config, root, height)?;
+            let output = prove::dry_run(config, &state, batch)?;
+            let new_state_root: String = HexString::from(output.state_root()).into();
+            let new_nullifier_root: String = HexString::from(output.nullifier_root()).into();
+            println!("Execution succeeded in {} cycles", output.cycles);
+            println!("New state root = {new_state_root}");
+            println!("New nullifier root = {new_nullifier_root}");
+            println!("The Rust implementation gives the same roots");
+        }
+        Command::Verify {
+            tx_path,
+            batch_path,
+            root,
+            height,
+        } => {
+            let batch = read_batch(tx_path, batch_path)?;
+            let state = load_state(config, root, height)?;
+            let (new_state_root, new_nullifier_root) =
+                verify::execute(config, &state, &batch, &config.outputs_path)?;
+            let new_state_root: String = HexString::from(new_state_root).into();
+            let new_nullifier_root: String = HexString::from(new_nullifier_root).into();
+            println!("Proof verified");
+            println!("New state root = {new_state_root}");
+            println!("New nullifier root = {new_nullifier_root}");
+        }
+        Command::Serve { port } => serve::execute(config, port)?,
         Command::NoZk(sub_command) => sub_command.execute(config)?,
+        Command::Wallet(sub_command) => sub_command.execute(config)?,
     }
 
     Ok(())
 }
+
+/// Read a batch from a batch file (or directory), or a single signed transaction file.
+fn read_batch(tx_path: Option<String>, batch_path: Option<String>) -> anyhow::Result<Batch> {
+    match (tx_path, batch_path) {
+        (_, Some(batch_path)) => utils::read_batch(Path::new(&batch_path)),
+        (Some(tx_path), None) => {
+            let signed_tx: SerializedSignedTransaction =
+                utils::read_json_file(Path::new(&tx_path))?;
+            Ok(SignedTransaction::try_from(signed_tx)?.into())
+        }
+        (None, None) => anyhow::bail!("Either a transaction or a batch must be given"),
+    }
+}
+
+/// Load the state from the configured store, either the latest one or the one with the given root,
+/// and move it to the given height.
+fn load_state(
+    config: &Config,
+    root: Option<HexString>,
+    height: Option<u64>,
+) -> anyhow::Result<State> {
+    let store = Store::new(&config.store_path);
+    let mut state = match root {
+        Some(root) => store.load_at(root.try_into()?)?,
+        None => store.load()?,
+    };
+    if let Some(height) = height {
+        state.set_height(height)?;
+    }
+    Ok(state)
+}
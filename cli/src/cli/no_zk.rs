@@ -1,32 +1,132 @@
-use crate::{
-    config::Config,
-    utils::{self, HexString},
-    utxo::{Key, SerializedUtxo, SignedTransaction, State, Transaction, Utxo},
-};
+use crate::config::Config;
 use anyhow::Context;
 use clap::Subcommand;
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use utxo_miden::{
+    genesis,
+    keystore::EncryptedKey,
+    storage::Store,
+    utils::{self, HexString},
+    utxo::{
+        Key, MintPolicy, Multisig, SerializedSignedTransaction, SerializedUtxo, SignedTransaction,
+        Signer, State, Transaction, Utxo, NATIVE_ASSET,
+    },
+    wallet::{self, KeyFile},
+    KeyPair, Word,
+};
 
 #[derive(Subcommand)]
 pub enum Command {
-    /// Generate a new key pair to use for signing UTXO transactions
+    /// Generate a new key pair to use for signing UTXO transactions. The key file is encrypted
+    /// with a passphrase, read from the `UTXO_MIDEN_PASSPHRASE` environment variable or asked for
     GenerateKeyPair,
-    /// Create a new state with a single UTXO in it
+    /// Encrypt the plaintext key files of the key directory with a passphrase (given as for
+    /// `GenerateKeyPair`). Multisig key sets and encrypted key files are left as they are
+    EncryptKeyFiles,
+    /// Create an owner which needs the signatures of `threshold` of the given keys
+    /// (the key set is written next to the key files, named after the owner)
+    CreateMultisig {
+        #[clap(short, long)]
+        threshold: usize,
+        #[clap(short, long = "key", required = true)]
+        keys: Vec<HexString>,
+    },
+    /// Create a new state with a single UTXO in it, starting a new state history
     CreateState {
         #[clap(short, long)]
         owner: HexString,
+        /// Asset of the UTXO (the native asset if not given).
+        #[clap(short, long)]
+        asset_id: Option<HexString>,
         #[clap(short, long)]
         value: HexString,
+        /// Serial of the UTXO (all zeros if not given).
+        #[clap(short, long)]
+        serial: Option<HexString>,
+        /// Owner allowed to mint more of the asset of the UTXO (a key or a multisig owner).
+        /// The asset cannot be minted if not given.
+        #[clap(long)]
+        issuer: Option<HexString>,
+        /// Maximum total value the issuer can mint (no limit if not given).
+        #[clap(long, requires = "issuer")]
+        cap: Option<u64>,
+    },
+    /// Create a new state with one UTXO per allocation of an allocation list, starting a new
+    /// state history. The list is a JSON array (see `SerializedAllocation`) or a CSV file with
+    /// `owner,value[,asset_id]` lines. A genesis manifest with the state root and the total
+    /// value of each asset is written next to the key files.
+    Genesis {
+        #[clap(short, long)]
+        allocations_path: PathBuf,
     },
     /// Send a transaction, updating the state.
-    /// A key file must exist for the signer (one can be created via `GenerateKeyPair`).
+    /// A key file must exist for each signer (one can be created via `GenerateKeyPair`).
+    /// There must be one signer per transaction input, given in the same order as the inputs,
+    /// followed by the issuer of the minted asset if the transaction mints.
+    /// A signer can be a multisig owner (see `CreateMultisig`), in which case it is signed
+    /// with the first `threshold` of its keys which have a key file.
     /// The transaction is specified as a JSON file (see `SerializedTransaction`).
+    /// This is the same as `Sign` followed by `Submit`.
     ProcessTransaction {
+        #[clap(short, long = "signer", required = true)]
+        signers: Vec<HexString>,
+        #[clap(short, long)]
+        tx_path: String,
+        /// Height to process the transaction at (the height of the latest state by default).
+        /// It cannot be lower than the height of the latest state.
+        #[clap(long)]
+        height: Option<u64>,
+    },
+    /// Send `amount` of an asset to another owner, updating the state. The inputs are selected
+    /// among the UTXOs of the sender which can be spent at the latest height, and the rest of
+    /// their value goes back to the sender as change.
+    /// The sender must have a key file (or be a multisig owner, see `ProcessTransaction`).
+    Send {
         #[clap(short, long)]
-        signer: HexString,
+        from: HexString,
+        #[clap(short, long)]
+        to: HexString,
+        #[clap(short, long)]
+        amount: u64,
+        /// Asset to send (the native asset if not given).
+        #[clap(long)]
+        asset_id: Option<HexString>,
+        /// Fee paid in the native asset.
+        #[clap(long, default_value_t = 0)]
+        fee: u64,
+        /// Write the signed transaction to this file (see `SerializedSignedTransaction`)
+        /// instead of applying it to the state.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Sign a transaction without processing it, so that it can be submitted later (see
+    /// `Submit`) by someone holding the state. Only the key files of the signers are needed,
+    /// given as for `ProcessTransaction`. The signed transaction (see
+    /// `SerializedSignedTransaction`) is printed, or written to a file if one is given.
+    Sign {
+        #[clap(short, long = "signer", required = true)]
+        signers: Vec<HexString>,
         #[clap(short, long)]
         tx_path: String,
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Apply a transaction signed with `Sign` (or `Send --output`), updating the state.
+    Submit {
+        #[clap(short, long)]
+        signed_tx_path: String,
+        /// Height to process the transaction at (the height of the latest state by default).
+        /// It cannot be lower than the height of the latest state.
+        #[clap(long)]
+        height: Option<u64>,
     },
+    /// List the roots the state has had, from the oldest to the latest
+    History,
+    /// List the UTXOs in the latest state with their hashes (to use as transaction inputs)
+    ListUtxos,
 }
 
 impl Command {
@@ -34,44 +134,289 @@ impl Command {
         match self {
             Self::GenerateKeyPair => {
                 let key = Key::random()?;
-                let output = serde_json::to_string_pretty(&key)?;
-                let owner: HexString = key.owner.into();
-                let output_path = config
-                    .no_zk_path
-                    .join(format!("{}.json", hex::encode(owner.bytes)));
+                let encrypted = EncryptedKey::encrypt(&key, passphrase(true)?)?;
+                let output = serde_json::to_string_pretty(&encrypted)?;
+                let output_path = key_path(config, &key.owner.into());
                 std::fs::write(&output_path, output)?;
                 println!("Key written to {output_path:?}");
             }
-            Self::CreateState { owner, value } => {
-                let mut state = State::empty();
-                let initial_utxo = Utxo::try_from(SerializedUtxo { owner, value })?;
+            Self::EncryptKeyFiles => {
+                let wallet = wallet::Wallet::load(&config.no_zk_path)?;
+                let mut count = 0;
+                for (owner, _) in wallet.owners {
+                    let path = key_path(config, &owner.into());
+                    let KeyFile::Key(key) = utils::read_json_file(&path)? else {
+                        continue;
+                    };
+                    let encrypted = EncryptedKey::encrypt(&key, passphrase(true)?)?;
+                    // Replace the file in one step, so that the key cannot be lost half-way
+                    let tmp_path = path.with_extension("json.tmp");
+                    std::fs::write(&tmp_path, serde_json::to_string_pretty(&encrypted)?)?;
+                    std::fs::rename(&tmp_path, &path)?;
+                    println!("Key file {path:?} encrypted");
+                    count += 1;
+                }
+                println!("{count} key files encrypted");
+            }
+            Self::CreateMultisig { threshold, keys } => {
+                let keys = keys
+                    .into_iter()
+                    .map(Word::try_from)
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let multisig = Multisig::new(threshold, keys)?;
+                let owner: HexString = multisig.owner().into();
+                let output_path = key_path(config, &owner);
+                std::fs::write(&output_path, serde_json::to_string_pretty(&multisig)?)?;
+                let owner: String = owner.into();
+                println!("Multisig owner = {owner}");
+                println!("Key set written to {output_path:?}");
+            }
+            Self::CreateState {
+                owner,
+                asset_id,
+                value,
+                serial,
+                issuer,
+                cap,
+            } => {
+                let mut state = State::empty(config.state_tree_depth)?;
+                let serial = serial.unwrap_or_else(|| Word::default().into());
+                let initial_utxo = Utxo::try_from(SerializedUtxo {
+                    owner,
+                    asset_id,
+                    value,
+                    serial,
+                    unlock_height: None,
+                })?;
+                if let Some(issuer) = issuer {
+                    let policy = MintPolicy::new(initial_utxo.asset_id, issuer.try_into()?, cap);
+                    state.insert_policy(policy)?;
+                }
                 state.insert(initial_utxo)?;
-                utils::write_state(&state, config)?;
+                Store::create(&config.store_path, &state)?;
+                print_state_root(&state);
+                println!("State history started in {:?}", config.store_path);
+            }
+            Self::Genesis { allocations_path } => {
+                let allocations = genesis::read_allocations(&allocations_path)
+                    .context("Failed to read allocations file")?;
+                let (state, manifest) = genesis::build(config.state_tree_depth, &allocations)?;
+                let manifest_path = config.no_zk_path.join("genesis.json");
+                Store::create(&config.store_path, &state)?;
+                std::fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+                print_state_root(&state);
+                println!("State history started in {:?}", config.store_path);
+                println!("Genesis manifest written to {manifest_path:?}");
             }
-            Self::ProcessTransaction { signer, tx_path } => {
-                let signer: String = signer.into();
-                let key_path = config.no_zk_path.join(format!(
-                    "{}.json",
-                    signer.strip_prefix("0x").unwrap_or(&signer)
-                ));
-                let state_path = config.no_zk_path.join("state.json");
-                let key: Key =
-                    utils::read_json_file(&key_path).context("Failed to read key file")?;
-                let mut state: State =
-                    utils::read_json_file(&state_path).context("Failed to read state file")?;
+            Self::ProcessTransaction {
+                signers,
+                tx_path,
+                height,
+            } => {
                 let transaction: Transaction = utils::read_json_file(Path::new(&tx_path))
                     .context("Failed to read transaction file")?;
+                let signed_transaction = sign(config, &signers, transaction)?;
+                submit(config, signed_transaction, height)?;
+            }
+            Self::Sign {
+                signers,
+                tx_path,
+                output,
+            } => {
+                let transaction: Transaction = utils::read_json_file(Path::new(&tx_path))
+                    .context("Failed to read transaction file")?;
+                let signed_transaction = sign(config, &signers, transaction)?;
+                match output {
+                    Some(output_path) => {
+                        write_signed_transaction(signed_transaction, &output_path)?
+                    }
+                    None => {
+                        let serialized = SerializedSignedTransaction::from(signed_transaction);
+                        println!("{}", serde_json::to_string_pretty(&serialized)?);
+                    }
+                }
+            }
+            Self::Submit {
+                signed_tx_path,
+                height,
+            } => {
+                let signed_transaction: SerializedSignedTransaction =
+                    utils::read_json_file(Path::new(&signed_tx_path))
+                        .context("Failed to read signed transaction file")?;
+                submit(config, signed_transaction.try_into()?, height)?;
+            }
+            Self::Send {
+                from,
+                to,
+                amount,
+                asset_id,
+                fee,
+                output,
+            } => {
+                let state = Store::new(&config.store_path).load()?;
+                let asset_id = match asset_id {
+                    Some(asset_id) => asset_id.try_into()?,
+                    None => NATIVE_ASSET,
+                };
+                let transaction = wallet::transfer(
+                    &state,
+                    from.clone().try_into()?,
+                    to.try_into()?,
+                    asset_id,
+                    amount,
+                    fee,
+                )?;
+                let signers = vec![from; transaction.inputs.len()];
+                let signed_transaction = sign(config, &signers, transaction)?;
+                match output {
+                    Some(output_path) => {
+                        write_signed_transaction(signed_transaction, &output_path)?
+                    }
+                    None => submit(config, signed_transaction, None)?,
+                }
+            }
+            Self::History => {
+                for root in Store::new(&config.store_path).roots()? {
+                    let root: String = HexString::from(root).into();
+                    println!("{root}");
+                }
+            }
+            Self::ListUtxos => {
+                let state = Store::new(&config.store_path).load()?;
+                for utxo in state.utxos.into_values() {
+                    let hash: String = HexString::from(utxo.hash()).into();
+                    let utxo = serde_json::to_string(&utxo)?;
+                    println!("{hash} {utxo}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
 
-                let signed_transaction = SignedTransaction::new(transaction, key.pair)
-                    .context("Failed to sign transaction")?;
-                state
-                    .process_tx(signed_transaction)
-                    .context("Error processing transaction")?;
+/// Sign the transaction with the key files of the signers (see `ProcessTransaction`).
+fn sign(
+    config: &Config,
+    signers: &[HexString],
+    transaction: Transaction,
+) -> anyhow::Result<SignedTransaction> {
+    let signer_keys = signers
+        .iter()
+        .map(|signer| read_signer(config, signer))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let signers: Vec<Signer> = signer_keys
+        .iter()
+        .map(|(multisig, keys)| match multisig {
+            Some(owner) => Signer::Multisig { owner, keys },
+            None => Signer::Key(&keys[0]),
+        })
+        .collect();
+    SignedTransaction::with_signers(transaction, &signers).context("Failed to sign transaction")
+}
+
+/// Apply the signed transaction to the latest state (moved to the given height first, if any)
+/// and append it to the store.
+fn submit(
+    config: &Config,
+    signed_transaction: SignedTransaction,
+    height: Option<u64>,
+) -> anyhow::Result<()> {
+    let store = Store::new(&config.store_path);
+    let mut state = store.load()?;
+    if let Some(height) = height {
+        state.set_height(height)?;
+    }
+    let fee_collector = config.fee_collector(std::slice::from_ref(&signed_transaction))?;
+    store
+        .apply(&mut state, signed_transaction.into(), fee_collector)
+        .context("Error processing transaction")?;
+
+    print_state_root(&state);
+    println!("State appended to {:?}", config.store_path);
+    Ok(())
+}
+
+fn write_signed_transaction(
+    signed_transaction: SignedTransaction,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    let serialized = SerializedSignedTransaction::from(signed_transaction);
+    std::fs::write(output_path, serde_json::to_string_pretty(&serialized)?)?;
+    println!("Signed transaction written to {output_path:?}");
+    Ok(())
+}
 
-                utils::write_state(&state, config)?;
+/// Key file (or multisig key set) of the given owner.
+fn key_path(config: &Config, owner: &HexString) -> PathBuf {
+    config
+        .no_zk_path
+        .join(format!("{}.json", hex::encode(&owner.bytes)))
+}
+
+/// Environment variable holding the passphrase of the key files.
+const PASSPHRASE_VAR: &str = "UTXO_MIDEN_PASSPHRASE";
+
+/// Passphrase of the key files, from `PASSPHRASE_VAR` or asked for (twice if it is used to
+/// encrypt, in case of a typo). It is only read once per run.
+fn passphrase(confirm: bool) -> anyhow::Result<&'static str> {
+    static PASSPHRASE: OnceLock<String> = OnceLock::new();
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase);
+    }
+    let passphrase = match std::env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let passphrase = rpassword::prompt_password("Key file passphrase: ")?;
+            if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+                anyhow::bail!("The passphrases do not match");
             }
+            passphrase
         }
+    };
+    Ok(PASSPHRASE.get_or_init(|| passphrase))
+}
 
-        Ok(())
+/// Read the key of the given owner, decrypting it if the key file is encrypted.
+fn read_key(config: &Config, owner: &HexString) -> anyhow::Result<Key> {
+    let file: KeyFile =
+        utils::read_json_file(&key_path(config, owner)).context("Failed to read key file")?;
+    match file {
+        KeyFile::Key(key) => Ok(key),
+        KeyFile::EncryptedKey(key) => key
+            .decrypt(passphrase(false)?)
+            .context("Failed to decrypt key file"),
+        KeyFile::Multisig(_) => anyhow::bail!("The owner is a multisig owner, not a key"),
+    }
+}
+
+/// Read the keys to sign with for the given owner, together with its key set if it is a multisig.
+fn read_signer(
+    config: &Config,
+    owner: &HexString,
+) -> anyhow::Result<(Option<Multisig>, Vec<KeyPair>)> {
+    let file: KeyFile =
+        utils::read_json_file(&key_path(config, owner)).context("Failed to read key file")?;
+    match file {
+        KeyFile::Key(key) => Ok((None, vec![key.pair])),
+        KeyFile::EncryptedKey(_) => Ok((None, vec![read_key(config, owner)?.pair])),
+        KeyFile::Multisig(multisig) => {
+            let mut keys = Vec::with_capacity(multisig.threshold);
+            for key in multisig.keys.iter() {
+                let path = key_path(config, &(*key).into());
+                if keys.len() < multisig.threshold && path.exists() {
+                    keys.push(read_key(config, &(*key).into())?.pair);
+                }
+            }
+            if keys.len() < multisig.threshold {
+                anyhow::bail!("Not enough key files to sign for the multisig owner");
+            }
+            Ok((Some(multisig), keys))
+        }
     }
 }
+
+fn print_state_root(state: &State) {
+    let state_root: String = HexString::from(state.get_root()).into();
+    println!("State root = {state_root}");
+}
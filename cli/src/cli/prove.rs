@@ -1,86 +1,53 @@
-use crate::{
-    advice_provider::UtxoAdvice,
-    config::Config,
-    utils,
-    utxo::{SignedTransaction, State},
-};
+use crate::config::Config;
 use anyhow::Context;
-use miden::{
-    math::Felt, Assembler, DefaultHost, ExecutionProof, ProvingOptions, StackInputs, StackOutputs,
+use utxo_miden::{
+    prove::{self, ProveOutput, RunOutput},
+    utxo::BatchError,
+    Batch, Program, State,
 };
-use miden_stdlib::StdLibrary;
-use std::path::Path;
-
-#[derive(Debug, Clone)]
-pub struct ProveOutput {
-    pub program_hash: [u8; 32],
-    pub stack_outputs: StackOutputs,
-    pub proof: ExecutionProof,
-}
-
-pub fn execute(config: &Config, signed_tx: SignedTransaction) -> anyhow::Result<ProveOutput> {
-    let code = std::fs::read_to_string(&config.code_path)?;
-    let assembler = Assembler::default().with_library(&StdLibrary::default())?;
-    let program = assembler.compile(code)?;
-    let state: State =
-        utils::read_json_file(&config.state_path).context("Failed to read state file")?;
-
-    let stack_inputs = prepare_stack_inputs(&state, &signed_tx);
-    let advice_provider = UtxoAdvice::new(&state, signed_tx)
-        .ok_or_else(|| anyhow::Error::msg("Input UTXO not present in the state"))?;
-    let host = DefaultHost::new(advice_provider);
 
-    let (stack_outputs, proof) =
-        miden::prove(&program, stack_inputs, host, ProvingOptions::default())?;
-
-    Ok(ProveOutput {
-        program_hash: program.hash().into(),
-        stack_outputs,
-        proof,
-    })
+pub fn execute(config: &Config, state: &State, batch: Batch) -> anyhow::Result<ProveOutput> {
+    let program = compile_program(config, state)?;
+    let fee_collector = config.fee_collector(&batch.transactions)?;
+    prove::prove(&program, state, batch, fee_collector)
 }
 
-impl ProveOutput {
-    pub fn write_to_file(&self, path: &Path) -> anyhow::Result<()> {
-        let serialized_outputs: Vec<String> = self
-            .stack_outputs
-            .stack()
-            .iter()
-            .map(|x| x.to_string())
-            .collect();
-        let serialized_overflows: Vec<String> = self
-            .stack_outputs
-            .overflow_addrs()
-            .iter()
-            .map(|x| x.to_string())
-            .collect();
-        let serialized_proof = hex::encode(self.proof.to_bytes());
-        let json_encoding = serde_json::json!({
-            "program_hash": format!("0x{}", hex::encode(self.program_hash)),
-            "stack_outputs": serialized_outputs,
-            "overflow_addrs": serialized_overflows,
-            "proof": serialized_proof,
-        });
-
-        let data = serde_json::to_string_pretty(&json_encoding)?;
-        std::fs::write(path, data)?;
-
-        Ok(())
+/// Execute the program on the batch without proving it, and check the result matches the
+/// Rust implementation applied to a copy of the state (the same roots, or the same error).
+pub fn dry_run(config: &Config, state: &State, batch: Batch) -> anyhow::Result<RunOutput> {
+    let program = compile_program(config, state)?;
+    let fee_collector = config.fee_collector(&batch.transactions)?;
+    let result = prove::run(&program, state, batch.clone(), fee_collector);
+    let mut expected_state = state.clone();
+    match (result, expected_state.process_batch(batch, fee_collector)) {
+        (Ok(output), Ok(())) => {
+            if output.state_root() != expected_state.get_root()
+                || output.nullifier_root() != expected_state.get_nullifier_root()
+            {
+                anyhow::bail!("The program and the Rust implementation give different roots");
+            }
+            Ok(output)
+        }
+        (Err(error), Err(expected)) => {
+            if error.downcast_ref::<BatchError>() == Some(&expected) {
+                Err(error).context("Execution failed")
+            } else {
+                Err(error).context(format!(
+                    "Execution failed (the Rust implementation fails with {expected:?})"
+                ))
+            }
+        }
+        (Ok(_), Err(expected)) => Err(expected)
+            .context("The program accepts the batch but the Rust implementation rejects it"),
+        (Err(error), Ok(())) => {
+            Err(error).context("Execution failed but the Rust implementation accepts the batch")
+        }
     }
 }
 
-// The operand stack starts as transaction_size then transaction hash and finally state root
-pub fn prepare_stack_inputs(state: &State, signed_tx: &SignedTransaction) -> StackInputs {
-    let tx_size = Felt::new(signed_tx.transaction.to_elems().len() as u64);
-    let transaction_hash = signed_tx.transaction.hash();
-    let state_root = state.get_root();
-
-    // Insert stack elements in reverse, stack top is at the rear
-    let input_stack: Vec<Felt> = state_root
-        .into_iter()
-        .chain(transaction_hash)
-        .chain(std::iter::once(tx_size))
-        .collect();
-
-    StackInputs::new(input_stack)
+/// Compile the configured MASM program for the given state. The tree depth is taken from the
+/// state, whatever the configured depth is (it only applies to newly created states).
+pub fn compile_program(config: &Config, state: &State) -> anyhow::Result<Program> {
+    let code = std::fs::read_to_string(&config.code_path)?;
+    prove::compile_program(&code, state.tree_depth())
 }
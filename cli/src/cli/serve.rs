@@ -0,0 +1,583 @@
+//! Local JSON-RPC node.
+//!
+//! The node listens on localhost only and answers JSON-RPC 2.0 requests sent as the body
+//! of HTTP POST requests. Submitted transactions wait in the mempool until a batch of them
+//! is applied, which appends the batch to the configured state store. Proofs are generated
+//! on demand against the state each batch was applied to, in the background so that the
+//! node keeps answering requests meanwhile.
+
+use crate::{cli::prove, config::Config};
+use anyhow::Context;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    thread::JoinHandle,
+    time::Duration,
+};
+use utxo_miden::{
+    advice_provider::raw_word,
+    mempool::{Mempool, OrderingPolicy},
+    prove::SerializedProveOutput,
+    storage::{BatchRecord, Store},
+    utils::HexString,
+    utxo::{BatchError, SerializedSignedTransaction, SerializedUtxo, SignedTransaction, State},
+    Word,
+};
+
+// JSON-RPC error codes (see https://www.jsonrpc.org/specification#error_object)
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// The request is well formed but could not be carried out (e.g. the transaction is rejected)
+const EXECUTION_ERROR: i64 = -32000;
+
+/// Largest request body the node reads, larger requests are rejected without reading them.
+const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+/// Largest number of headers of a request.
+const MAX_HEADERS: usize = 100;
+/// Longest request line or header line.
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+/// Time a client has to send its request or read the response, so that a client which
+/// stays idle cannot stall the node for longer.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn execute(config: &Config, port: u16) -> anyhow::Result<()> {
+    let mut node = Node::new(config)?;
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .with_context(|| format!("Failed to listen on port {port}"))?;
+    println!("Listening on http://{}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| node.handle_connection(stream));
+        if let Err(e) = result {
+            eprintln!("Connection error: {e:?}");
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Request {
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Response {
+    pub jsonrpc: &'static str,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HashParams {
+    hash: HexString,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct OwnerParams {
+    owner: HexString,
+}
+
+/// Which pending transactions make up the next batch.
+#[derive(Debug, serde::Deserialize)]
+struct BatchParams {
+    #[serde(default)]
+    policy: OrderingPolicy,
+    #[serde(default = "default_max_transactions")]
+    max_transactions: usize,
+    /// Height to apply the batch at (only used by `applyBatch`, the current height by default).
+    #[serde(default)]
+    height: Option<u64>,
+}
+
+impl Default for BatchParams {
+    fn default() -> Self {
+        Self {
+            policy: OrderingPolicy::default(),
+            max_transactions: default_max_transactions(),
+            height: None,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct UtxoEntry {
+    hash: HexString,
+    #[serde(flatten)]
+    utxo: SerializedUtxo,
+}
+
+pub struct Node<'a> {
+    config: &'a Config,
+    store: Store,
+    state: State,
+    fee_collector: Word,
+    mempool: Mempool,
+    /// Batches of the store, keyed by batch hash.
+    batches: BTreeMap<[u64; 4], BatchRecord>,
+    /// Hash of the applied batch containing each transaction, keyed by transaction hash.
+    transaction_batches: BTreeMap<[u64; 4], Word>,
+    /// Proofs generated (or being generated) so far, keyed by batch hash.
+    proofs: BTreeMap<[u64; 4], Proof>,
+}
+
+/// Proof of an applied batch, which is generated in a thread of its own.
+enum Proof {
+    Pending(JoinHandle<anyhow::Result<Value>>),
+    Done(Value),
+}
+
+impl<'a> Node<'a> {
+    pub fn new(config: &'a Config) -> anyhow::Result<Self> {
+        let store = Store::new(&config.store_path);
+        let state = store.load()?;
+        // Transactions paying a fee are rejected on submission without a fee collector
+        let fee_collector = config.fee_collector(&[])?;
+        // The batches applied before a restart can still be proven
+        let mut batches = BTreeMap::new();
+        let mut transaction_batches = BTreeMap::new();
+        for record in store.batches()? {
+            let batch_hash = record.batch.hash();
+            for tx in record.batch.transactions.iter() {
+                transaction_batches.insert(raw_word(tx.transaction.hash()), batch_hash);
+            }
+            batches.insert(raw_word(batch_hash), record);
+        }
+        Ok(Self {
+            config,
+            store,
+            state,
+            fee_collector,
+            mempool: Mempool::new(fee_collector),
+            batches,
+            transaction_batches,
+            proofs: BTreeMap::new(),
+        })
+    }
+
+    pub fn handle(&mut self, request: Request) -> Response {
+        let (result, error) = match self.call(&request.method, request.params) {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Response {
+            jsonrpc: "2.0",
+            id: request.id,
+            result,
+            error,
+        }
+    }
+
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "submitTransaction" => {
+                let signed_tx: SerializedSignedTransaction = parse_params(params)?;
+                let signed_tx = SignedTransaction::try_from(signed_tx)
+                    .map_err(|e| rpc_error(INVALID_PARAMS, e))?;
+                self.config
+                    .fee_collector(std::slice::from_ref(&signed_tx))
+                    .map_err(|e| rpc_error(INVALID_PARAMS, e))?;
+                let hash = self
+                    .mempool
+                    .add(&self.state, signed_tx)
+                    .map_err(|e| rpc_error(EXECUTION_ERROR, e))?;
+                Ok(json!({ "transaction_hash": HexString::from(hash) }))
+            }
+            "getPendingTransactions" => {
+                let params: Option<BatchParams> = parse_params(params)?;
+                let params = params.unwrap_or_default();
+                let hashes: Vec<HexString> = self
+                    .mempool
+                    .ordered(params.policy)
+                    .into_iter()
+                    .take(params.max_transactions)
+                    .map(|tx| tx.transaction.hash().into())
+                    .collect();
+                Ok(json!(hashes))
+            }
+            "applyBatch" => {
+                let params: Option<BatchParams> = parse_params(params)?;
+                let params = params.unwrap_or_default();
+                self.apply_batch(params.policy, params.max_transactions, params.height)
+            }
+            "getStateRoot" => Ok(json!(HexString::from(self.state.get_root()))),
+            "getUtxo" => {
+                let HashParams { hash } = parse_params(params)?;
+                let hash: Word = hash.try_into().map_err(|e| rpc_error(INVALID_PARAMS, e))?;
+                let utxo = self
+                    .state
+                    .get_utxo(&hash)
+                    .cloned()
+                    .map(SerializedUtxo::from);
+                Ok(json!(utxo))
+            }
+            "getUtxosByOwner" => {
+                let OwnerParams { owner } = parse_params(params)?;
+                let owner: Word = owner.try_into().map_err(|e| rpc_error(INVALID_PARAMS, e))?;
+                let utxos: Vec<UtxoEntry> = self
+                    .state
+                    .utxos
+                    .values()
+                    .filter(|utxo| utxo.owner == owner)
+                    .map(|utxo| UtxoEntry {
+                        hash: utxo.hash().into(),
+                        utxo: utxo.clone().into(),
+                    })
+                    .collect();
+                Ok(json!(utxos))
+            }
+            "getProof" => {
+                let HashParams { hash } = parse_params(params)?;
+                let hash: Word = hash.try_into().map_err(|e| rpc_error(INVALID_PARAMS, e))?;
+                let batch_hash = self
+                    .transaction_batches
+                    .get(&raw_word(hash))
+                    .copied()
+                    .ok_or_else(|| {
+                        rpc_error(EXECUTION_ERROR, "Transaction not in an applied batch")
+                    })?;
+                self.prove_batch(batch_hash)
+            }
+            _ => Err(rpc_error(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {method}"),
+            )),
+        }
+    }
+
+    /// Apply a batch of pending transactions, then evict the pending transactions it invalidated.
+    fn apply_batch(
+        &mut self,
+        policy: OrderingPolicy,
+        max_transactions: usize,
+        height: Option<u64>,
+    ) -> Result<Value, RpcError> {
+        let batch = self
+            .mempool
+            .next_batch(policy, max_transactions)
+            .ok_or_else(|| rpc_error(EXECUTION_ERROR, "No pending transactions"))?;
+        let root = self.state.get_root();
+        // The batch is applied to a copy of the state, which only replaces the state once the
+        // batch is in the log, so that the state never gets ahead of the log
+        let mut state = self.state.clone();
+        if let Some(height) = height {
+            state
+                .set_height(height)
+                .map_err(|e| rpc_error(INVALID_PARAMS, e))?;
+        }
+        if let Err(e) = self
+            .store
+            .apply(&mut state, batch.clone(), self.fee_collector)
+        {
+            // A transaction which cannot be applied is dropped so that the next batch can succeed
+            if let Some(BatchError::InvalidTransaction { index, .. }) = e.downcast_ref() {
+                self.mempool
+                    .remove(batch.transactions[*index].transaction.hash());
+            }
+            return Err(rpc_error(EXECUTION_ERROR, e));
+        }
+        self.state = state;
+
+        let batch_hash = batch.hash();
+        let transactions: Vec<HexString> = batch
+            .transactions
+            .iter()
+            .map(|tx| {
+                let hash = tx.transaction.hash();
+                self.transaction_batches.insert(raw_word(hash), batch_hash);
+                hash.into()
+            })
+            .collect();
+        let evicted: Vec<HexString> = self
+            .mempool
+            .evict_invalid(&self.state)
+            .into_iter()
+            .map(|tx| tx.transaction.hash())
+            .filter(|hash| !self.transaction_batches.contains_key(&raw_word(*hash)))
+            .map(HexString::from)
+            .collect();
+        let record = BatchRecord {
+            batch,
+            fee_collector: self.fee_collector,
+            height: self.state.height,
+            previous_root: root,
+        };
+        self.batches.insert(raw_word(batch_hash), record);
+
+        Ok(json!({
+            "batch_hash": HexString::from(batch_hash),
+            "state_root": HexString::from(self.state.get_root()),
+            "transactions": transactions,
+            "evicted": evicted,
+        }))
+    }
+
+    /// The proof of an applied batch, together with the batch, the state root it was
+    /// applied to and its height (as needed to verify the proof). The first request starts
+    /// generating the proof in the background: until it is done the `status` is `pending`
+    /// and the proof is `null`. If it fails, the error is returned and the next request
+    /// starts again.
+    fn prove_batch(&mut self, batch_hash: Word) -> Result<Value, RpcError> {
+        let key = raw_word(batch_hash);
+        let record = self
+            .batches
+            .get(&key)
+            .ok_or_else(|| rpc_error(EXECUTION_ERROR, "Unknown batch"))?;
+        let proof = match self.proofs.remove(&key) {
+            Some(Proof::Pending(handle)) if handle.is_finished() => {
+                let proof = handle
+                    .join()
+                    .map_err(|_| rpc_error(EXECUTION_ERROR, "Proof generation panicked"))?
+                    .map_err(|e| rpc_error(EXECUTION_ERROR, e))?;
+                Proof::Done(proof)
+            }
+            Some(proof) => proof,
+            None => {
+                let mut state = self
+                    .store
+                    .load_at(record.previous_root)
+                    .map_err(|e| rpc_error(EXECUTION_ERROR, e))?;
+                state.height = record.height;
+                let program = prove::compile_program(self.config, &state)
+                    .map_err(|e| rpc_error(EXECUTION_ERROR, e))?;
+                let batch = record.batch.clone();
+                let fee_collector = record.fee_collector;
+                Proof::Pending(std::thread::spawn(move || {
+                    let output = utxo_miden::prove::prove(&program, &state, batch, fee_collector)?;
+                    Ok(json!(SerializedProveOutput::from(&output)))
+                }))
+            }
+        };
+        let (status, proof_value) = match &proof {
+            Proof::Pending(_) => ("pending", Value::Null),
+            Proof::Done(value) => ("done", value.clone()),
+        };
+        self.proofs.insert(key, proof);
+
+        let transactions: Vec<SerializedSignedTransaction> = record
+            .batch
+            .transactions
+            .iter()
+            .cloned()
+            .map(Into::into)
+            .collect();
+        Ok(json!({
+            "batch": transactions,
+            "state_root": HexString::from(record.previous_root),
+            "height": record.height,
+            "status": status,
+            "proof": proof_value,
+        }))
+    }
+
+    fn handle_connection(&mut self, stream: TcpStream) -> anyhow::Result<()> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let Some(request_line) = read_line(&mut reader)? else {
+            return write_response(stream, "431 Request Header Fields Too Large", "");
+        };
+
+        let mut content_length = 0;
+        for i in 0.. {
+            let Some(header) = read_line(&mut reader)? else {
+                return write_response(stream, "431 Request Header Fields Too Large", "");
+            };
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if i == MAX_HEADERS {
+                return write_response(stream, "431 Request Header Fields Too Large", "");
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().context("Invalid content length")?;
+                }
+            }
+        }
+        if content_length > MAX_BODY_SIZE {
+            return write_response(stream, "413 Payload Too Large", "");
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        let (status, body) = if request_line.starts_with("POST ") {
+            ("200 OK", self.handle_body(&body)?)
+        } else {
+            ("405 Method Not Allowed", String::new())
+        };
+        write_response(stream, status, &body)
+    }
+
+    fn handle_body(&mut self, body: &[u8]) -> anyhow::Result<String> {
+        let response = match serde_json::from_slice::<Value>(body) {
+            Err(e) => error_response(rpc_error(PARSE_ERROR, e)),
+            Ok(value) => match serde_json::from_value::<Request>(value) {
+                Err(e) => error_response(rpc_error(INVALID_REQUEST, e)),
+                Ok(request) => self.handle(request),
+            },
+        };
+        Ok(serde_json::to_string(&response)?)
+    }
+}
+
+/// Read a line of at most `MAX_LINE_LENGTH` bytes, or `None` if it is longer.
+fn read_line(reader: &mut impl BufRead) -> anyhow::Result<Option<String>> {
+    let mut line = String::new();
+    reader
+        .take(MAX_LINE_LENGTH as u64 + 1)
+        .read_line(&mut line)?;
+    Ok((line.len() <= MAX_LINE_LENGTH).then_some(line))
+}
+
+fn default_max_transactions() -> usize {
+    usize::MAX
+}
+
+fn parse_params<T: for<'a> serde::Deserialize<'a>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| rpc_error(INVALID_PARAMS, e))
+}
+
+fn rpc_error(code: i64, message: impl std::fmt::Display) -> RpcError {
+    RpcError {
+        code,
+        message: format!("{message:#}"),
+    }
+}
+
+fn error_response(error: RpcError) -> Response {
+    Response {
+        jsonrpc: "2.0",
+        id: Value::Null,
+        result: None,
+        error: Some(error),
+    }
+}
+
+fn write_response(mut stream: TcpStream, status: &str, body: &str) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test_node() {
+    use utxo_miden::{utxo::NATIVE_ASSET, Felt, Key, Output, Transaction, Utxo};
+
+    let store_path =
+        std::env::temp_dir().join(format!("utxo-miden-node-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&store_path);
+    let config = Config {
+        store_path: store_path.clone(),
+        fee_collector: Some(HexString::from(Key::random().unwrap().owner)),
+        ..Config::default()
+    };
+
+    let key = Key::random().unwrap();
+    let initial_utxo = Utxo {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(config.state_tree_depth).unwrap();
+    state.insert(initial_utxo.clone()).unwrap();
+    Store::create(&store_path, &state).unwrap();
+
+    let mut node = Node::new(&config).unwrap();
+    assert_eq!(
+        node.call("getStateRoot", Value::Null).unwrap(),
+        json!(HexString::from(state.get_root()))
+    );
+
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![Output {
+            owner: key.owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(90),
+            unlock_height: None,
+        }],
+        fee: Felt::new(10),
+        mint: None,
+    };
+    let signed_tx = SignedTransaction::new(transaction, &[key.pair]).unwrap();
+    let params = json!(SerializedSignedTransaction::from(signed_tx));
+
+    // The fee cannot be collected without a fee collector
+    let no_collector = Config {
+        store_path: store_path.clone(),
+        ..Config::default()
+    };
+    let mut no_collector_node = Node::new(&no_collector).unwrap();
+    let error = no_collector_node
+        .call("submitTransaction", params.clone())
+        .unwrap_err();
+    assert_eq!(error.code, INVALID_PARAMS);
+
+    node.call("submitTransaction", params.clone()).unwrap();
+
+    // The transaction is already pending
+    let error = node.call("submitTransaction", params.clone()).unwrap_err();
+    assert_eq!(error.code, EXECUTION_ERROR);
+
+    let result = node
+        .call("applyBatch", json!({ "policy": "highest_fee" }))
+        .unwrap();
+    assert_eq!(result["transactions"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        node.call("getPendingTransactions", Value::Null).unwrap(),
+        json!([])
+    );
+
+    // The input is spent, so submitting again fails
+    let error = node.call("submitTransaction", params).unwrap_err();
+    assert_eq!(error.code, EXECUTION_ERROR);
+
+    let spent = json!({ "hash": HexString::from(initial_utxo.hash()) });
+    assert_eq!(node.call("getUtxo", spent).unwrap(), Value::Null);
+    let owned = node
+        .call(
+            "getUtxosByOwner",
+            json!({ "owner": HexString::from(key.owner) }),
+        )
+        .unwrap();
+    assert_eq!(owned.as_array().unwrap().len(), 1);
+
+    let error = node.call("getBalance", Value::Null).unwrap_err();
+    assert_eq!(error.code, METHOD_NOT_FOUND);
+
+    // The node picks up the history written by the previous one
+    let restarted = Node::new(&config).unwrap();
+    assert_eq!(restarted.state.get_root(), node.state.get_root());
+    // And can still find the batch of the transaction
+    assert_eq!(restarted.batches.len(), 1);
+    assert_eq!(restarted.transaction_batches, node.transaction_batches);
+
+    std::fs::remove_file(&store_path).unwrap();
+}
@@ -0,0 +1,18 @@
+use crate::{cli::prove, config::Config};
+use std::path::Path;
+use utxo_miden::{prove::ProveOutput, verify, Batch, State, Word};
+
+/// Verify a proof written by `prove::execute` for the given batch against the
+/// given state. On success the new state root and nullifier root (taken from the proof
+/// outputs) are returned.
+pub fn execute(
+    config: &Config,
+    state: &State,
+    batch: &Batch,
+    proof_path: &Path,
+) -> anyhow::Result<(Word, Word)> {
+    let output = ProveOutput::read_from_file(proof_path)?;
+    let program = prove::compile_program(config, state)?;
+    let fee_collector = config.fee_collector(&batch.transactions)?;
+    verify::verify(&program, state, batch, fee_collector, output)
+}
@@ -0,0 +1,77 @@
+use crate::config::Config;
+use clap::Subcommand;
+use utxo_miden::{storage::Store, utils::HexString, utxo::NATIVE_ASSET, wallet::Wallet, Word};
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Show the balance of each asset for every owner with a key file (or multisig key set),
+    /// in the latest state
+    Balance {
+        /// Only show the balance of this owner.
+        #[clap(short, long)]
+        owner: Option<HexString>,
+    },
+    /// List the UTXOs in the latest state owned by an owner with a key file (or multisig
+    /// key set), with their hashes (to use as transaction inputs)
+    List {
+        /// Only list the UTXOs of this owner.
+        #[clap(short, long)]
+        owner: Option<HexString>,
+    },
+}
+
+impl Command {
+    pub fn execute(self, config: &Config) -> anyhow::Result<()> {
+        let mut wallet = Wallet::load(&config.no_zk_path)?;
+        let state = Store::new(&config.store_path).load()?;
+        match self {
+            Self::Balance { owner } => {
+                filter_owner(&mut wallet, owner)?;
+                for (owner, is_multisig) in wallet.owners.iter() {
+                    let owner_hex: String = HexString::from(*owner).into();
+                    match is_multisig {
+                        true => println!("{owner_hex} (multisig)"),
+                        false => println!("{owner_hex}"),
+                    }
+                    let balances = Wallet::balances(&state, owner);
+                    if balances.is_empty() {
+                        println!("  no UTXOs");
+                    }
+                    for balance in balances {
+                        let asset: String = if balance.asset_id == NATIVE_ASSET {
+                            "native".into()
+                        } else {
+                            HexString::from(balance.asset_id).into()
+                        };
+                        match balance.locked {
+                            0 => println!("  {asset}: {}", balance.value),
+                            locked => println!("  {asset}: {} ({locked} locked)", balance.value),
+                        }
+                    }
+                }
+            }
+            Self::List { owner } => {
+                filter_owner(&mut wallet, owner)?;
+                for (hash, utxo) in wallet.utxos(&state) {
+                    let hash: String = HexString::from(hash).into();
+                    let utxo = serde_json::to_string(utxo)?;
+                    println!("{hash} {utxo}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Keep only the given owner in the wallet, which must have a key file.
+fn filter_owner(wallet: &mut Wallet, owner: Option<HexString>) -> anyhow::Result<()> {
+    if let Some(owner) = owner {
+        let owner: Word = owner.try_into()?;
+        if !wallet.contains(&owner) {
+            anyhow::bail!("There is no key file for the owner");
+        }
+        wallet.owners.retain(|(o, _)| o == &owner);
+    }
+    Ok(())
+}
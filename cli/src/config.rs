@@ -1,15 +1,24 @@
 use std::path::{Path, PathBuf};
+use utxo_miden::{utils::HexString, SignedTransaction, State, Word};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Config {
     /// Path to the file with UTXO MidenVM assemby (masm) code to execute.
     pub code_path: PathBuf,
-    /// Path to the file with UTXO state used to initialize MidenVM advice provider.
-    pub state_path: PathBuf,
+    /// Path to the log file holding the history of the UTXO state (see `storage::Store`).
+    pub store_path: PathBuf,
     /// Path to the file where output from the CLI are written
     pub outputs_path: PathBuf,
     /// Directory where data from no-zk part of the CLI is saved.
     pub no_zk_path: PathBuf,
+    /// Depth of the sparse Merkle tree holding the UTXOs of newly created states.
+    /// The MASM program is always assembled with the depth of the state it runs against.
+    #[serde(default = "default_state_tree_depth")]
+    pub state_tree_depth: u8,
+    /// Owner of the UTXOs created from transaction fees.
+    /// If not present transactions paying a fee are rejected.
+    #[serde(default)]
+    pub fee_collector: Option<HexString>,
 }
 
 impl Config {
@@ -18,6 +27,21 @@ impl Config {
         let config = serde_json::from_str(&data)?;
         Ok(config)
     }
+
+    /// The fee collector to process the transactions with. It can only be left out if none of
+    /// them pays a fee (as the fee would be burned otherwise), in which case it is unused.
+    pub fn fee_collector(&self, transactions: &[SignedTransaction]) -> anyhow::Result<Word> {
+        match &self.fee_collector {
+            Some(owner) => owner.clone().try_into(),
+            None if transactions
+                .iter()
+                .any(|tx| tx.transaction.fee.as_int() != 0) =>
+            {
+                anyhow::bail!("A transaction pays a fee but no fee_collector is configured")
+            }
+            None => Ok(Word::default()),
+        }
+    }
 }
 
 impl Default for Config {
@@ -25,9 +49,15 @@ impl Default for Config {
         let base_path = Path::new("masm");
         Self {
             code_path: base_path.join("utxo.masm"),
-            state_path: base_path.join("state.json"),
+            store_path: Path::new("example").join("state.jsonl"),
             outputs_path: base_path.join("utxo.outputs"),
             no_zk_path: Path::new("example").into(),
+            state_tree_depth: default_state_tree_depth(),
+            fee_collector: None,
         }
     }
 }
+
+fn default_state_tree_depth() -> u8 {
+    State::DEFAULT_TREE_DEPTH
+}
@@ -1,14 +1,8 @@
 use clap::Parser;
 use config::Config;
 
-mod advice_provider;
 mod cli;
 mod config;
-mod utils;
-mod utxo;
-
-#[cfg(test)]
-mod masm_tests;
 
 fn main() -> anyhow::Result<()> {
     let args = cli::Cli::parse();
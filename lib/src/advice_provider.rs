@@ -0,0 +1,283 @@
+use crate::utxo::{Batch, InputSignature, MintPolicy, SignedTransaction, State};
+use miden::{math::Felt, AdviceInputs, AdviceProvider, ExecutionError, MemAdviceProvider, Word};
+use miden_core::{SignatureKind, WORD_SIZE};
+use miden_crypto::{
+    dsa::rpo_falcon512::Polynomial, hash::rpo::RpoDigest, merkle::MerkleStore, StarkField,
+};
+use miden_processor::ProcessState;
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+pub struct UtxoAdvice {
+    inner: MemAdviceProvider,
+    known_transactions: HashMap<[u64; 4], SignedTransaction>,
+    /// Owner of each signature of the known transactions (the input owners and the issuer).
+    known_owners: HashMap<[u64; 4], Vec<Word>>,
+    /// Position in the batch of the transaction the program is processing.
+    current_transaction: Rc<Cell<Option<usize>>>,
+}
+
+impl UtxoAdvice {
+    pub fn new(state: &State, batch: Batch, fee_collector: Word) -> Self {
+        // Merkle store contains the state and the nullifiers
+        let mut merkle_store = MerkleStore::default();
+        merkle_store.extend(state.tree.inner_nodes());
+        merkle_store.extend(state.nullifiers.inner_nodes());
+
+        // The serialized batch is in the advice map under the batch hash
+        let mut map: BTreeMap<[u8; 32], Vec<Felt>> = BTreeMap::new();
+        map.insert(map_key(batch.hash()), batch.to_elems());
+
+        // Transactions are applied to a copy of the state so that inputs created
+        // by earlier transactions in the batch can be found.
+        let mut state = state.clone();
+        let mut known_owners = HashMap::new();
+        let mut known_transactions = HashMap::new();
+        for signed_tx in batch.transactions {
+            // Each serialized transaction is in the advice map under its hash
+            let tx_hash = signed_tx.transaction.hash();
+            map.insert(map_key(tx_hash), signed_tx.transaction.to_elems());
+
+            // Input UTXOs are in the advice map under their hashes, followed by the signers
+            // of the input (see `push_signers`). The minting policy of the minted asset is
+            // in the advice map under its hash, followed by the signers of the issuer.
+            let mut owners = Vec::new();
+            let mut signatures = signed_tx.signatures.iter();
+            for input in signed_tx.transaction.inputs.iter() {
+                if let (Some(input_utxo), Some(signature)) =
+                    (state.get_utxo(input), signatures.next())
+                {
+                    let mut elems = input_utxo.serialize();
+                    push_signers(&mut elems, &mut map, input_utxo.owner, signature);
+                    map.insert(map_key(*input), elems);
+                    owners.push(input_utxo.owner);
+                }
+            }
+            if let Some(mint) = signed_tx.transaction.mint.as_ref() {
+                match state.get_policy(&mint.asset_id) {
+                    Some(policy) => {
+                        let issuer_signature = signed_tx.transaction.inputs.len();
+                        if let Some(signature) = signed_tx.signatures.get(issuer_signature) {
+                            let mut elems = policy.to_elems();
+                            push_signers(&mut elems, &mut map, policy.issuer, signature);
+                            map.insert(map_key(policy.hash()), elems);
+                            owners.push(policy.issuer);
+                        }
+                    }
+                    // Without a policy for the asset the program is given an empty one under
+                    // the leaf of the policy (unless the leaf is already a key of the map),
+                    // which does not match the leaf, so it fails with INVALID_MINT_POLICY
+                    // instead of a missing advice map key
+                    None => {
+                        let index = state.leaf_index(&MintPolicy::key(mint.asset_id));
+                        let leaf = state.tree.get_leaf(index).unwrap_or_default();
+                        map.entry(map_key(leaf))
+                            .or_insert_with(|| vec![Felt::new(0); 3 * WORD_SIZE]);
+                    }
+                }
+            }
+            known_owners.insert(raw_word(tx_hash), owners);
+
+            // If the transaction is invalid then the program fails on it, so
+            // the state after it does not matter.
+            let _ = state.process_tx(signed_tx.clone(), fee_collector);
+            known_transactions.insert(raw_word(tx_hash), signed_tx);
+        }
+
+        let advice_inputs = AdviceInputs::default()
+            .with_map(map)
+            .with_merkle_store(merkle_store);
+
+        Self {
+            inner: MemAdviceProvider::from(advice_inputs),
+            known_transactions,
+            known_owners,
+            current_transaction: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Position in the batch of the transaction the program is processing (the last one it
+    /// started loading), which is `None` before the first one. The cell is shared with the
+    /// advice provider, so it can still be read once the provider is consumed by the
+    /// execution, e.g. to find which transaction made it fail.
+    pub fn current_transaction(&self) -> Rc<Cell<Option<usize>>> {
+        self.current_transaction.clone()
+    }
+}
+
+/// Add the number of multisig signers and their key indices to the advice data of a UTXO or
+/// minting policy (no signers for single key owners). The key set of a multisig owner is put
+/// in the advice map under the owner.
+fn push_signers(
+    elems: &mut Vec<Felt>,
+    map: &mut BTreeMap<[u8; 32], Vec<Felt>>,
+    owner: Word,
+    signature: &InputSignature,
+) {
+    match signature {
+        InputSignature::Single(_) => elems.push(Felt::new(0)),
+        InputSignature::Multisig {
+            owner: multisig,
+            signatures,
+        } => {
+            elems.push(Felt::new(signatures.len() as u64));
+            elems.extend(signatures.iter().map(|(i, _)| Felt::new(*i as u64)));
+            map.insert(map_key(owner), multisig.to_elems());
+        }
+    }
+}
+
+impl AdviceProvider for UtxoAdvice {
+    fn pop_stack<S: ProcessState>(&mut self, process: &S) -> Result<Felt, ExecutionError> {
+        self.inner.pop_stack(process)
+    }
+
+    fn pop_stack_word<S: ProcessState>(&mut self, process: &S) -> Result<Word, ExecutionError> {
+        self.inner.pop_stack_word(process)
+    }
+
+    fn pop_stack_dword<S: ProcessState>(
+        &mut self,
+        process: &S,
+    ) -> Result<[Word; 2], ExecutionError> {
+        self.inner.pop_stack_dword(process)
+    }
+
+    fn push_stack(&mut self, source: miden_processor::AdviceSource) -> Result<(), ExecutionError> {
+        // The program loads the transactions of the batch in order (see `load_transaction`)
+        if let miden_processor::AdviceSource::Map { key, .. } = &source {
+            if self.known_transactions.contains_key(&raw_word(*key)) {
+                let index = self.current_transaction.get().map_or(0, |index| index + 1);
+                self.current_transaction.set(Some(index));
+            }
+        }
+        self.inner.push_stack(source)
+    }
+
+    fn get_mapped_values(&self, key: &[u8; 32]) -> Option<&[Felt]> {
+        self.inner.get_mapped_values(key)
+    }
+
+    fn insert_into_map(&mut self, key: Word, values: Vec<Felt>) -> Result<(), ExecutionError> {
+        self.inner.insert_into_map(key, values)
+    }
+
+    fn get_signature(
+        &self,
+        kind: SignatureKind,
+        pub_key: Word,
+        msg: Word,
+    ) -> Result<Vec<Felt>, ExecutionError> {
+        match kind {
+            SignatureKind::RpoFalcon512 => {
+                let key = raw_word(msg);
+                let signed_tx = self.known_transactions.get(&key).ok_or_else(|| {
+                    ExecutionError::FailedSignatureGeneration("Unknown transaction hash")
+                })?;
+                // Use the (first) signature made by the given key, either as the owner of
+                // an input (or the issuer) or as one of the keys of a multisig owner
+                let owners = self.known_owners.get(&key).into_iter().flatten();
+                let sig = owners
+                    .zip(signed_tx.signatures.iter())
+                    .find_map(|(owner, signature)| match signature {
+                        InputSignature::Single(sig) => (owner == &pub_key).then_some(sig),
+                        InputSignature::Multisig { owner, signatures } => signatures
+                            .iter()
+                            .find(|(index, _)| owner.keys.get(*index) == Some(&pub_key))
+                            .map(|(_, sig)| sig),
+                    })
+                    .ok_or_else(|| {
+                        ExecutionError::FailedSignatureGeneration("Invalid pub key for transaction")
+                    })?;
+
+                // For details on this signature post-processing, see
+                // ...
+                let nonce = sig.nonce();
+                let s2 = sig.sig_poly();
+                let h = sig.pub_key_poly();
+                let pi = Polynomial::mul_modulo_p(&h, &s2);
+
+                let mut result: Vec<Felt> = nonce.to_vec();
+                result.extend(h.inner().iter().map(|a| Felt::from(*a)));
+                result.extend(s2.inner().iter().map(|a| Felt::from(*a)));
+                result.extend(pi.iter().map(|a| Felt::new(*a)));
+                result.reverse();
+                Ok(result)
+            }
+        }
+    }
+
+    fn get_tree_node(
+        &self,
+        root: Word,
+        depth: &Felt,
+        index: &Felt,
+    ) -> Result<Word, ExecutionError> {
+        self.inner.get_tree_node(root, depth, index)
+    }
+
+    fn get_merkle_path(
+        &self,
+        root: Word,
+        depth: &Felt,
+        index: &Felt,
+    ) -> Result<miden_crypto::merkle::MerklePath, ExecutionError> {
+        self.inner.get_merkle_path(root, depth, index)
+    }
+
+    fn get_leaf_depth(
+        &self,
+        root: Word,
+        tree_depth: &Felt,
+        index: &Felt,
+    ) -> Result<u8, ExecutionError> {
+        self.inner.get_leaf_depth(root, tree_depth, index)
+    }
+
+    fn find_lone_leaf(
+        &self,
+        root: Word,
+        root_index: miden_crypto::merkle::NodeIndex,
+        tree_depth: u8,
+    ) -> Result<Option<(miden_crypto::merkle::NodeIndex, Word)>, ExecutionError> {
+        self.inner.find_lone_leaf(root, root_index, tree_depth)
+    }
+
+    fn update_merkle_node(
+        &mut self,
+        root: Word,
+        depth: &Felt,
+        index: &Felt,
+        value: Word,
+    ) -> Result<(miden_crypto::merkle::MerklePath, Word), ExecutionError> {
+        self.inner.update_merkle_node(root, depth, index, value)
+    }
+
+    fn merge_roots(&mut self, lhs: Word, rhs: Word) -> Result<Word, ExecutionError> {
+        self.inner.merge_roots(lhs, rhs)
+    }
+
+    fn get_store_subset<I, R>(&self, roots: I) -> miden_crypto::merkle::MerkleStore
+    where
+        I: Iterator<Item = R>,
+        R: std::borrow::Borrow<miden_processor::Digest>,
+    {
+        self.inner.get_store_subset(roots)
+    }
+}
+
+/// Advice map keys are the bytes of the word used to look them up in MASM.
+fn map_key(word: Word) -> [u8; 32] {
+    RpoDigest::from(word).as_bytes()
+}
+
+pub fn raw_word(word: Word) -> [u64; 4] {
+    let mut output = [0; 4];
+    for (el, o) in word.into_iter().zip(output.iter_mut()) {
+        *o = el.as_int();
+    }
+    output
+}
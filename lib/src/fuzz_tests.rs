@@ -0,0 +1,511 @@
+//! Differential fuzzing of the Rust implementation against the MASM program.
+//!
+//! Random states and transactions (valid ones and ones with any number of defects) are applied
+//! with `State::process_batch` and executed in MidenVM with `prove::run`: either both succeed
+//! with the same roots, or both fail with the same error. Both implementations check a
+//! transaction in the same order (see `State::process_tx`), so defects are freely combined,
+//! and a transaction fails if and only if it has one (or, in a small tree, one of its outputs
+//! lands on an occupied leaf).
+//! The seed and the number of transactions can be set with the `FUZZ_SEED` and `FUZZ_CASES`
+//! environment variables, e.g. to reproduce a failure or run a longer session.
+
+use crate::{
+    prove,
+    utxo::{
+        Batch, BatchError, Key, Mint, MintPolicy, Multisig, Output, SignedTransaction, Signer,
+        State, StateError, Transaction, Utxo, MAX_SUPPLY, MAX_VALUE, NATIVE_ASSET,
+    },
+    Program,
+};
+use miden_crypto::{dsa::rpo_falcon512::KeyPair, Felt, StarkField, Word};
+use std::collections::BTreeMap;
+
+/// Number of transactions applied to each random state.
+const CASES_PER_STATE: u64 = 8;
+
+/// Depths of the trees of the random states. In the small trees outputs often land on
+/// occupied leaves.
+const TREE_DEPTHS: [u8; 3] = [3, 4, State::DEFAULT_TREE_DEPTH];
+
+/// Maximum number of defects of a transaction.
+const MAX_DEFECTS: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Defect {
+    /// The first input is signed with a key which does not own it.
+    BadSignature,
+    /// The outputs take more than the inputs.
+    Overspend,
+    /// Part of the inputs is neither spent by the outputs nor paid as a fee.
+    Underspend,
+    /// One of the inputs is not in the state.
+    UnknownInput,
+    /// One of the inputs is still locked.
+    LockedInput,
+    /// The first input is owned by the multisig and signed by fewer keys than its threshold.
+    MissingMultisigSignature,
+    /// The first input is owned by the multisig and signed for with another key set.
+    InvalidMultisig,
+    /// One of the outputs has a value above `MAX_VALUE`.
+    ValueOverflow,
+    /// The transaction mints an asset without a minting policy.
+    UnknownAsset,
+    /// The transaction mints a value of 0.
+    EmptyMint,
+    /// The mint is not made for the current minted total of the policy.
+    InvalidMintedSupply,
+    /// The mint takes the minted total above the cap of the policy (or `MAX_SUPPLY`).
+    MintCapExceeded,
+    /// The mint is signed with a key which is not the issuer.
+    BadIssuerSignature,
+}
+
+const DEFECTS: [Defect; 13] = [
+    Defect::BadSignature,
+    Defect::Overspend,
+    Defect::Underspend,
+    Defect::UnknownInput,
+    Defect::LockedInput,
+    Defect::MissingMultisigSignature,
+    Defect::InvalidMultisig,
+    Defect::ValueOverflow,
+    Defect::UnknownAsset,
+    Defect::EmptyMint,
+    Defect::InvalidMintedSupply,
+    Defect::MintCapExceeded,
+    Defect::BadIssuerSignature,
+];
+
+/// Defects which make the transaction mint.
+const MINT_DEFECTS: [Defect; 5] = [
+    Defect::UnknownAsset,
+    Defect::EmptyMint,
+    Defect::InvalidMintedSupply,
+    Defect::MintCapExceeded,
+    Defect::BadIssuerSignature,
+];
+
+/// Small deterministic random number generator (SplitMix64), so that a failure can be
+/// reproduced from its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+
+    fn word(&mut self) -> Word {
+        [(); 4].map(|_| Felt::new(self.below(Felt::MODULUS)))
+    }
+}
+
+/// Owners of the UTXOs and minting policies of the random states: a few keys and a 2-of-3
+/// multisig of them.
+struct Owners {
+    keys: Vec<Key>,
+    multisig: Multisig,
+}
+
+impl Owners {
+    fn random() -> Self {
+        let keys: Vec<Key> = (0..3).map(|_| Key::random().unwrap()).collect();
+        let multisig = Multisig::new(2, keys.iter().map(|key| key.owner).collect()).unwrap();
+        Self { keys, multisig }
+    }
+
+    /// One of the keys, or the multisig a quarter of the time.
+    fn pick(&self, rng: &mut Rng) -> Word {
+        if rng.below(4) == 0 {
+            self.multisig.owner()
+        } else {
+            rng.pick(&self.keys).owner
+        }
+    }
+
+    /// Key which is not the given owner.
+    fn other_key(&self, owner: Word) -> KeyPair {
+        self.keys
+            .iter()
+            .find(|key| key.owner != owner)
+            .unwrap()
+            .pair
+    }
+
+    /// Valid signing for the owner. The multisig is signed for by `threshold` random keys, none
+    /// of them `excluded`.
+    fn signing(&self, rng: &mut Rng, owner: Word, excluded: Option<Word>) -> Signing {
+        if owner == self.multisig.owner() {
+            let mut keys: Vec<KeyPair> = self
+                .keys
+                .iter()
+                .filter(|key| Some(key.owner) != excluded)
+                .map(|key| key.pair)
+                .collect();
+            while keys.len() > self.multisig.threshold {
+                keys.swap_remove(rng.below(keys.len() as u64) as usize);
+            }
+            Signing::Multisig(self.multisig.clone(), keys)
+        } else {
+            let key = self.keys.iter().find(|key| key.owner == owner).unwrap();
+            Signing::Key(key.pair)
+        }
+    }
+}
+
+/// Keys signing for one input (or the mint) of a random transaction, see `Signer`.
+enum Signing {
+    Key(KeyPair),
+    Multisig(Multisig, Vec<KeyPair>),
+}
+
+#[test]
+fn test_fuzz_vm_matches_rust() {
+    let seed = env_var("FUZZ_SEED").unwrap_or(0x5eed);
+    let cases = env_var("FUZZ_CASES").unwrap_or(24);
+    let mut rng = Rng(seed);
+    let owners = Owners::random();
+    let asset_id = [Felt::new(7), Felt::new(8), Felt::new(9), Felt::new(10)];
+    let fee_collector = [Felt::new(1), Felt::new(2), Felt::new(3), Felt::new(4)];
+    let code = std::fs::read_to_string("../masm/utxo.masm").unwrap();
+    let mut programs: BTreeMap<u8, Program> = BTreeMap::new();
+
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    for case in 0..cases {
+        if case % CASES_PER_STATE == 0 {
+            let tree_depth = *rng.pick(&TREE_DEPTHS);
+            state = random_state(&mut rng, &owners, asset_id, tree_depth);
+        }
+        let defects = random_defects(&mut rng);
+        let height = state.height + rng.below(2);
+        state.set_height(height).unwrap();
+        let Some(signed_tx) = random_transaction(&mut rng, &state, &owners, asset_id, &defects)
+        else {
+            continue;
+        };
+        let batch: Batch = signed_tx.into();
+
+        let program = programs
+            .entry(state.tree_depth())
+            .or_insert_with(|| prove::compile_program(&code, state.tree_depth()).unwrap());
+        let vm_result = prove::run(program, &state, batch.clone(), fee_collector);
+        let mut expected_state = state.clone();
+        let result = expected_state.process_batch(batch, fee_collector);
+
+        let context = format!(
+            "seed {seed}, case {case}, tree depth {}, defects {defects:?}",
+            state.tree_depth()
+        );
+        // The leaves of the outputs are checked last, so an occupied one cannot hide a defect
+        let valid = matches!(
+            result,
+            Ok(())
+                | Err(BatchError::InvalidTransaction {
+                    error: StateError::LeafOccupied,
+                    ..
+                })
+        );
+        assert_eq!(valid, defects.is_empty(), "{context}: {result:?}");
+        match (vm_result, result) {
+            (Ok(output), Ok(())) => {
+                assert_eq!(output.state_root(), expected_state.get_root(), "{context}");
+                assert_eq!(
+                    output.nullifier_root(),
+                    expected_state.get_nullifier_root(),
+                    "{context}"
+                );
+                state = expected_state;
+            }
+            (Err(vm_error), Err(error)) => {
+                assert_eq!(
+                    vm_error.downcast_ref::<BatchError>(),
+                    Some(&error),
+                    "{context}: {vm_error:?}"
+                );
+            }
+            (Ok(_), Err(error)) => {
+                panic!("{context}: only the Rust implementation fails: {error:?}")
+            }
+            (Err(vm_error), Ok(())) => panic!("{context}: only MidenVM fails: {vm_error:?}"),
+        }
+    }
+}
+
+fn env_var(name: &str) -> Option<u64> {
+    let value = std::env::var(name).ok()?;
+    Some(
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("{name} must be a number")),
+    )
+}
+
+/// No defects for a third of the transactions, up to `MAX_DEFECTS` of them for the others.
+fn random_defects(rng: &mut Rng) -> Vec<Defect> {
+    let mut defects = Vec::new();
+    if rng.below(3) == 0 {
+        return defects;
+    }
+    for _ in 0..1 + rng.below(MAX_DEFECTS) {
+        let defect = *rng.pick(&DEFECTS);
+        if !defects.contains(&defect) {
+            defects.push(defect);
+        }
+    }
+    // Both change the value of the first output, so together they could cancel out
+    if defects.contains(&Defect::Overspend) {
+        defects.retain(|defect| *defect != Defect::Underspend);
+    }
+    defects
+}
+
+/// State with the minting policy of `asset_id` and a few UTXOs of the native asset and of
+/// `asset_id`, some of them locked for the first heights. The policy has either a cap or
+/// none (with a minted total close to `MAX_SUPPLY`), and can mint up to 1000 more.
+fn random_state(rng: &mut Rng, owners: &Owners, asset_id: Word, tree_depth: u8) -> State {
+    let mut state = State::empty(tree_depth).unwrap();
+    let cap = (rng.below(2) == 0).then(|| 1 + rng.below(1000));
+    let limit = cap.unwrap_or(MAX_SUPPLY);
+    let policy = MintPolicy {
+        minted: limit - 1 - rng.below(limit.min(1000)),
+        ..MintPolicy::new(asset_id, owners.pick(rng), cap)
+    };
+    state.insert_policy(policy).unwrap();
+    for _ in 0..2 + rng.below(6) {
+        let utxo = Utxo {
+            owner: owners.pick(rng),
+            asset_id: if rng.below(3) == 0 {
+                asset_id
+            } else {
+                NATIVE_ASSET
+            },
+            value: Felt::new(1 + rng.below(1000)),
+            serial: rng.word(),
+            unlock_height: (rng.below(4) == 0).then(|| 1 + rng.below(5)),
+        };
+        // The leaf of the UTXO can already be occupied in a small tree
+        let _ = state.insert(utxo);
+    }
+    state
+}
+
+/// Transaction spending up to three UTXOs, which conserves the value of each asset (paying
+/// part of the native value as a fee) unless the defects say otherwise. A third of the
+/// transactions without mint defects mint `asset_id` (if its policy can mint more), and
+/// those may have no inputs.
+/// There is no transaction if the state has nothing to spend (or to spend with the defects).
+fn random_transaction(
+    rng: &mut Rng,
+    state: &State,
+    owners: &Owners,
+    asset_id: Word,
+    defects: &[Defect],
+) -> Option<SignedTransaction> {
+    let has = |defect| defects.contains(&defect);
+
+    // Mint
+    let policy = state.get_policy(&asset_id);
+    let room = policy.map_or(0, |policy| policy.cap.unwrap_or(MAX_SUPPLY) - policy.minted);
+    let mints = defects.iter().any(|defect| MINT_DEFECTS.contains(defect))
+        || (room > 0 && rng.below(3) == 0);
+    let (mint, issuer) = match policy.filter(|_| mints && !has(Defect::UnknownAsset)) {
+        Some(policy) => {
+            let value = if has(Defect::EmptyMint) {
+                0
+            } else if has(Defect::MintCapExceeded) {
+                room + 1
+            } else {
+                1 + rng.below(room.max(1))
+            };
+            let minted = policy.minted + u64::from(has(Defect::InvalidMintedSupply));
+            let mint = Mint {
+                asset_id,
+                value: Felt::new(value),
+                minted,
+            };
+            (Some(mint), Some(policy.issuer))
+        }
+        // Without a policy (or with the `UnknownAsset` defect) a random asset is minted
+        None if mints => {
+            let mint = Mint {
+                asset_id: rng.word(),
+                value: Felt::new(u64::from(!has(Defect::EmptyMint)) * (1 + rng.below(1000))),
+                minted: 0,
+            };
+            (Some(mint), Some(owners.pick(rng)))
+        }
+        None => (None, None),
+    };
+    // MidenVM is given the first signature made by a key over the transaction, so the issuer
+    // key must not sign for anything else to find its bad signature
+    let excluded = issuer.filter(|issuer| {
+        has(Defect::BadIssuerSignature) && owners.keys.iter().any(|key| key.owner == *issuer)
+    });
+
+    // Inputs
+    let (locked, mut unlocked): (Vec<Utxo>, Vec<Utxo>) = state
+        .utxos
+        .values()
+        .filter(|utxo| Some(utxo.owner) != excluded)
+        .cloned()
+        .partition(|utxo| utxo.is_locked(state.height));
+    let mut inputs = Vec::new();
+    if has(Defect::MissingMultisigSignature) || has(Defect::InvalidMultisig) {
+        let multisig = owners.multisig.owner();
+        let index = unlocked.iter().position(|utxo| utxo.owner == multisig)?;
+        inputs.push(unlocked.swap_remove(index));
+    }
+    let count = if mint.is_some() {
+        rng.below(4)
+    } else {
+        1 + rng.below(3)
+    };
+    while (inputs.len() as u64) < count && !unlocked.is_empty() {
+        let index = rng.below(unlocked.len() as u64) as usize;
+        inputs.push(unlocked.swap_remove(index));
+    }
+    if has(Defect::LockedInput) {
+        if locked.is_empty() {
+            return None;
+        }
+        inputs.push(rng.pick(&locked).clone());
+    }
+    if inputs.is_empty() && (mint.is_none() || has(Defect::BadSignature)) {
+        return None;
+    }
+
+    // Outputs
+    let total = |asset| -> u64 {
+        let minted = mint
+            .iter()
+            .filter(|mint| mint.asset_id == asset)
+            .map(|mint| mint.value.as_int());
+        inputs
+            .iter()
+            .filter(|utxo| utxo.asset_id == asset)
+            .map(|utxo| utxo.value.as_int())
+            .chain(minted)
+            .sum()
+    };
+    let mut assets = vec![NATIVE_ASSET, asset_id];
+    if let Some(mint) = &mint {
+        if !assets.contains(&mint.asset_id) {
+            assets.push(mint.asset_id);
+        }
+    }
+    let native_total = total(NATIVE_ASSET);
+    // The rest of the native value is at least 1, so there is always an output for it
+    let fee = if native_total > 0 {
+        rng.below(native_total)
+    } else {
+        0
+    };
+    let mut outputs = Vec::new();
+    for &asset in assets.iter() {
+        let total = total(asset) - if asset == NATIVE_ASSET { fee } else { 0 };
+        if total == 0 {
+            continue;
+        }
+        let rest = rng.below(total);
+        for value in [total - rest, rest].into_iter().filter(|value| *value > 0) {
+            outputs.push(Output {
+                owner: owners.pick(rng),
+                asset_id: asset,
+                value: Felt::new(value),
+                unlock_height: (rng.below(4) == 0).then(|| state.height + 1 + rng.below(3)),
+            });
+        }
+    }
+    // The first output has a value of at least 1, and there is one unless nothing is spent
+    // nor minted
+    if has(Defect::Overspend) {
+        match outputs.first_mut() {
+            Some(output) => output.value += Felt::new(1),
+            None => outputs.push(Output {
+                owner: owners.pick(rng),
+                asset_id: NATIVE_ASSET,
+                value: Felt::new(1),
+                unlock_height: None,
+            }),
+        }
+    }
+    if has(Defect::Underspend) {
+        outputs.first_mut()?.value -= Felt::new(1);
+    }
+    if has(Defect::ValueOverflow) {
+        let index = rng.below(outputs.len() as u64 + 1) as usize;
+        let output = Output {
+            owner: owners.pick(rng),
+            asset_id: *rng.pick(&assets),
+            value: Felt::new(MAX_VALUE + 1 + rng.below(1000)),
+            unlock_height: None,
+        };
+        outputs.insert(index, output);
+    }
+
+    // Signatures
+    let mut signings: Vec<Signing> = inputs
+        .iter()
+        .map(|utxo| owners.signing(rng, utxo.owner, excluded))
+        .collect();
+    if has(Defect::MissingMultisigSignature) {
+        let key = rng.pick(&owners.keys).pair;
+        signings[0] = Signing::Multisig(owners.multisig.clone(), vec![key]);
+    }
+    if has(Defect::InvalidMultisig) {
+        // The same keys with a lower threshold
+        let multisig = Multisig::new(1, owners.multisig.keys.clone()).unwrap();
+        let key = rng.pick(&owners.keys).pair;
+        signings[0] = Signing::Multisig(multisig, vec![key]);
+    }
+    if has(Defect::BadSignature) {
+        signings[0] = Signing::Key(owners.other_key(inputs[0].owner));
+    }
+    if let Some(issuer) = issuer {
+        let signing = if has(Defect::BadIssuerSignature) {
+            Signing::Key(owners.other_key(issuer))
+        } else {
+            owners.signing(rng, issuer, None)
+        };
+        signings.push(signing);
+    }
+    let signers: Vec<Signer> = signings
+        .iter()
+        .map(|signing| match signing {
+            Signing::Key(key) => Signer::Key(key),
+            Signing::Multisig(owner, keys) => Signer::Multisig { owner, keys },
+        })
+        .collect();
+
+    let mut input_hashes: Vec<Word> = inputs.iter().map(|utxo| utxo.hash()).collect();
+    if has(Defect::UnknownInput) {
+        if inputs.is_empty() {
+            return None;
+        }
+        let index = rng.below(inputs.len() as u64) as usize;
+        input_hashes[index] = Utxo {
+            serial: rng.word(),
+            ..inputs[index].clone()
+        }
+        .hash();
+    }
+    let transaction = Transaction {
+        inputs: input_hashes,
+        outputs,
+        fee: Felt::new(fee),
+        mint,
+    };
+    Some(SignedTransaction::with_signers(transaction, &signers).unwrap())
+}
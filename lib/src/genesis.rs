@@ -0,0 +1,260 @@
+//! Genesis state built from a list of allocations.
+//!
+//! Every allocation becomes a UTXO of the genesis state, whose serial is given by the position
+//! of the allocation in the list (see `genesis_serial`). The allocation list is read from a JSON array (see `SerializedAllocation`) or from a CSV file
+//! with `owner,value[,asset_id]` lines, all fields given as hex strings.
+
+use crate::{
+    utils::{self, HexString},
+    utxo::{leaf_index_mask, State, StateError, Utxo, NATIVE_ASSET},
+};
+use anyhow::Context;
+use miden_crypto::{Felt, StarkField, Word, ZERO};
+use std::{fmt, path::Path, str::FromStr};
+
+/// Value given to an owner in the genesis state.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedAllocation", into = "SerializedAllocation")]
+pub struct Allocation {
+    pub owner: Word,
+    pub asset_id: Word,
+    pub value: Felt,
+}
+
+impl Allocation {
+    /// The UTXO created for the allocation at position `index` of the list in the genesis
+    /// state.
+    pub fn utxo(&self, index: usize) -> Utxo {
+        Utxo {
+            owner: self.owner,
+            asset_id: self.asset_id,
+            value: self.value,
+            serial: genesis_serial(index),
+            unlock_height: None,
+        }
+    }
+}
+
+/// Serial of the UTXO created for the allocation at position `index` of the list, so that
+/// equal allocations still give different UTXOs.
+pub fn genesis_serial(index: usize) -> Word {
+    [Felt::new(index as u64), ZERO, ZERO, ZERO]
+}
+
+/// Summary of a genesis state, written next to the state store so the genesis can be
+/// checked without replaying it.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct GenesisManifest {
+    pub root: HexString,
+    pub tree_depth: u8,
+    /// Number of UTXOs in the genesis state.
+    pub utxos: usize,
+    /// Total allocated value of each asset, in the order the assets first appear.
+    pub supply: Vec<AssetSupply>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct AssetSupply {
+    /// The native asset if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_id: Option<HexString>,
+    pub value: u64,
+}
+
+/// Build the genesis state (with a tree of the given depth) holding one UTXO per allocation.
+pub fn build(
+    tree_depth: u8,
+    allocations: &[Allocation],
+) -> Result<(State, GenesisManifest), GenesisError> {
+    if allocations.is_empty() {
+        return Err(GenesisError::Empty);
+    }
+    let mut state = State::empty(tree_depth).map_err(GenesisError::InvalidState)?;
+    if (allocations.len() - 1) as u64 > leaf_index_mask(tree_depth) {
+        return Err(GenesisError::ExceedsCapacity);
+    }
+
+    let mut supply: Vec<(Word, u64)> = Vec::new();
+    for (index, allocation) in allocations.iter().enumerate() {
+        state
+            .insert(allocation.utxo(index))
+            .map_err(|error| match error {
+                StateError::LeafOccupied => GenesisError::LeafOccupied { index },
+                error => GenesisError::InvalidState(error),
+            })?;
+        match supply.iter_mut().find(|(id, _)| id == &allocation.asset_id) {
+            Some((_, total)) => *total += allocation.value.as_int(),
+            None => supply.push((allocation.asset_id, allocation.value.as_int())),
+        }
+    }
+
+    let manifest = GenesisManifest {
+        root: state.get_root().into(),
+        tree_depth,
+        utxos: state.utxos.len(),
+        supply: supply
+            .into_iter()
+            .map(|(asset_id, value)| AssetSupply {
+                asset_id: (asset_id != NATIVE_ASSET).then(|| asset_id.into()),
+                value,
+            })
+            .collect(),
+    };
+    Ok((state, manifest))
+}
+
+/// Read an allocation list, as CSV if the file has a `.csv` extension and as JSON otherwise.
+pub fn read_allocations(path: &Path) -> anyhow::Result<Vec<Allocation>> {
+    if path.extension().map(|ext| ext == "csv").unwrap_or(false) {
+        let data = std::fs::read_to_string(path)?;
+        parse_csv(&data)
+    } else {
+        utils::read_json_file(path)
+    }
+}
+
+/// Parse `owner,value[,asset_id]` lines. Empty lines and a header line starting with
+/// `owner` are skipped.
+pub fn parse_csv(data: &str) -> anyhow::Result<Vec<Allocation>> {
+    let mut allocations = Vec::new();
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line.starts_with("owner")) {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let allocation = match fields[..] {
+            [owner, value] => parse_allocation(owner, value, None),
+            [owner, value, asset_id] => {
+                parse_allocation(owner, value, Some(asset_id).filter(|id| !id.is_empty()))
+            }
+            _ => Err(anyhow::anyhow!("Expected `owner,value[,asset_id]`")),
+        };
+        allocations
+            .push(allocation.with_context(|| format!("Invalid allocation on line {}", i + 1))?);
+    }
+    Ok(allocations)
+}
+
+fn parse_allocation(
+    owner: &str,
+    value: &str,
+    asset_id: Option<&str>,
+) -> anyhow::Result<Allocation> {
+    SerializedAllocation {
+        owner: HexString::from_str(owner)?,
+        asset_id: asset_id.map(HexString::from_str).transpose()?,
+        value: HexString::from_str(value)?,
+    }
+    .try_into()
+}
+
+#[derive(Debug)]
+pub enum GenesisError {
+    Empty,
+    /// There are more allocations than leaves in the state tree.
+    ExceedsCapacity,
+    /// The UTXO of the allocation at position `index` would be stored at the same leaf of the
+    /// state tree as an earlier one (which gets likelier the smaller the tree).
+    LeafOccupied {
+        index: usize,
+    },
+    InvalidState(StateError),
+}
+
+impl fmt::Display for GenesisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for GenesisError {}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedAllocation {
+    pub owner: HexString,
+    /// The native asset if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_id: Option<HexString>,
+    pub value: HexString,
+}
+
+impl TryFrom<SerializedAllocation> for Allocation {
+    type Error = anyhow::Error;
+
+    fn try_from(allocation: SerializedAllocation) -> Result<Self, Self::Error> {
+        let owner = allocation.owner.try_into()?;
+        let asset_id = match allocation.asset_id {
+            Some(asset_id) => asset_id.try_into()?,
+            None => NATIVE_ASSET,
+        };
+        let value = allocation.value.try_into()?;
+        Ok(Self {
+            owner,
+            asset_id,
+            value,
+        })
+    }
+}
+
+impl From<Allocation> for SerializedAllocation {
+    fn from(value: Allocation) -> Self {
+        Self {
+            owner: value.owner.into(),
+            asset_id: (value.asset_id != NATIVE_ASSET).then(|| value.asset_id.into()),
+            value: value.value.into(),
+        }
+    }
+}
+
+#[test]
+fn test_genesis() {
+    let csv = "owner,value,asset_id
+0x0100000000000000020000000000000003000000000000000400000000000000,0x6400000000000000
+0x0500000000000000060000000000000007000000000000000800000000000000,0x3200000000000000,0x0100000000000000000000000000000000000000000000000000000000000000
+
+0x0500000000000000060000000000000007000000000000000800000000000000,0x0a00000000000000,
+";
+    let mut allocations = parse_csv(csv).unwrap();
+    assert_eq!(allocations.len(), 3);
+    assert_eq!(allocations[2].asset_id, NATIVE_ASSET);
+
+    let (state, manifest) = build(State::DEFAULT_TREE_DEPTH, &allocations).unwrap();
+    let mut expected = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    for (index, allocation) in allocations.iter().enumerate() {
+        expected.insert(allocation.utxo(index)).unwrap();
+    }
+    assert_eq!(state.get_root(), expected.get_root());
+    assert_eq!(manifest.utxos, 3);
+    assert_eq!(manifest.supply.len(), 2);
+    assert_eq!(manifest.supply[0].value, 110);
+    assert_eq!(manifest.supply[1].value, 50);
+
+    let serialized = serde_json::to_string(&allocations).unwrap();
+    let deserialized: Vec<Allocation> = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(deserialized, allocations);
+
+    assert!(matches!(
+        build(1, &allocations),
+        Err(GenesisError::ExceedsCapacity)
+    ));
+    // Equal allocations give different UTXOs
+    allocations.push(allocations[0].clone());
+    let (state, manifest) = build(State::DEFAULT_TREE_DEPTH, &allocations).unwrap();
+    assert_ne!(allocations[0].utxo(0).hash(), allocations[3].utxo(3).hash());
+    assert_eq!(manifest.utxos, 4);
+    assert_eq!(manifest.supply[0].value, 210);
+    assert!(state.get_utxo(&allocations[3].utxo(3).hash()).is_some());
+    // But must still be stored at different leaves
+    let leaves: std::collections::BTreeSet<u64> = allocations
+        .iter()
+        .enumerate()
+        .map(|(index, allocation)| allocation.utxo(index).hash()[0].as_int() & leaf_index_mask(2))
+        .collect();
+    match build(2, &allocations) {
+        Ok(_) => assert_eq!(leaves.len(), allocations.len()),
+        Err(GenesisError::LeafOccupied { .. }) => assert!(leaves.len() < allocations.len()),
+        Err(error) => panic!("Unexpected error {error}"),
+    }
+    assert!(parse_csv("0x01").is_err());
+}
@@ -0,0 +1,241 @@
+//! Key files encrypted with a passphrase.
+//!
+//! The key pair is encrypted with XChaCha20-Poly1305, under a key derived from the
+//! passphrase with Argon2id. The owner is kept in the clear (so the owner of a key file is
+//! known without the passphrase) and is authenticated together with the encrypted key pair.
+
+use crate::{
+    utils::HexString,
+    utxo::{Key, SerializedKey},
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use miden_crypto::Word;
+use std::fmt;
+
+const SALT_SIZE: usize = 16;
+
+/// Maximum memory size of the key derivation (1 GiB), so that a key file cannot make
+/// decrypting it use unbounded memory or time.
+const MAX_M_COST: u32 = 1 << 20;
+/// Maximum number of iterations of the key derivation.
+const MAX_T_COST: u32 = 16;
+/// Maximum degree of parallelism of the key derivation.
+const MAX_P_COST: u32 = 16;
+
+/// Key pair encrypted with a passphrase (see `EncryptedKey::encrypt`).
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedEncryptedKey", into = "SerializedEncryptedKey")]
+pub struct EncryptedKey {
+    pub owner: Word,
+    pub kdf: KdfParams,
+    pub salt: Vec<u8>,
+    pub nonce: Vec<u8>,
+    /// The serialized key pair, followed by the authentication tag.
+    pub ciphertext: Vec<u8>,
+}
+
+/// Parameters of the Argon2id derivation of the encryption key from the passphrase.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct KdfParams {
+    /// Memory size in KiB.
+    pub m_cost: u32,
+    /// Number of iterations.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Whether the parameters are within the maximums accepted when decrypting.
+    fn is_bounded(&self) -> bool {
+        self.m_cost <= MAX_M_COST && self.t_cost <= MAX_T_COST && self.p_cost <= MAX_P_COST
+    }
+}
+
+impl EncryptedKey {
+    /// Encrypt the key with the passphrase, using a new random salt and nonce.
+    pub fn encrypt(key: &Key, passphrase: &str) -> Result<Self, KeystoreError> {
+        let kdf = KdfParams::default();
+        let mut salt = vec![0; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let cipher = cipher(passphrase, &kdf, &salt)?;
+        let pair = SerializedKey::from(key.clone()).pair.bytes;
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &pair,
+                    aad: &HexString::from(key.owner).bytes,
+                },
+            )
+            .map_err(|_| KeystoreError::Encryption)?;
+        Ok(Self {
+            owner: key.owner,
+            kdf,
+            salt,
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+
+    /// Decrypt the key with the passphrase it was encrypted with. The key derivation
+    /// parameters must not be above `MAX_M_COST`, `MAX_T_COST` and `MAX_P_COST`.
+    pub fn decrypt(&self, passphrase: &str) -> Result<Key, KeystoreError> {
+        if !self.kdf.is_bounded() {
+            return Err(KeystoreError::InvalidKdfParams);
+        }
+        if self.nonce.len() != 24 {
+            return Err(KeystoreError::InvalidNonce);
+        }
+        let owner = HexString::from(self.owner);
+        let cipher = cipher(passphrase, &self.kdf, &self.salt)?;
+        let pair = cipher
+            .decrypt(
+                XNonce::from_slice(&self.nonce),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: &owner.bytes,
+                },
+            )
+            .map_err(|_| KeystoreError::WrongPassphrase)?;
+        let key = Key::try_from(SerializedKey {
+            pair: HexString { bytes: pair },
+            owner,
+        })
+        .map_err(|_| KeystoreError::InvalidKeyPair)?;
+        if Word::from(key.pair.public_key()) != self.owner {
+            return Err(KeystoreError::InvalidKeyPair);
+        }
+        Ok(key)
+    }
+}
+
+/// Cipher keyed with the Argon2id hash of the passphrase.
+fn cipher(
+    passphrase: &str,
+    kdf: &KdfParams,
+    salt: &[u8],
+) -> Result<XChaCha20Poly1305, KeystoreError> {
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32))
+        .map_err(|_| KeystoreError::InvalidKdfParams)?;
+    let mut key = [0; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| KeystoreError::InvalidKdfParams)?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Encryption,
+    InvalidKdfParams,
+    InvalidNonce,
+    /// The key pair cannot be decrypted: the passphrase is wrong or the file was modified.
+    WrongPassphrase,
+    /// The decrypted key pair is invalid or does not belong to the owner.
+    InvalidKeyPair,
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedEncryptedKey {
+    pub owner: HexString,
+    pub kdf: KdfParams,
+    pub salt: HexString,
+    pub nonce: HexString,
+    pub ciphertext: HexString,
+}
+
+impl TryFrom<SerializedEncryptedKey> for EncryptedKey {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SerializedEncryptedKey) -> Result<Self, Self::Error> {
+        Ok(Self {
+            owner: value.owner.try_into()?,
+            kdf: value.kdf,
+            salt: value.salt.bytes,
+            nonce: value.nonce.bytes,
+            ciphertext: value.ciphertext.bytes,
+        })
+    }
+}
+
+impl From<EncryptedKey> for SerializedEncryptedKey {
+    fn from(value: EncryptedKey) -> Self {
+        Self {
+            owner: value.owner.into(),
+            kdf: value.kdf,
+            salt: HexString { bytes: value.salt },
+            nonce: HexString { bytes: value.nonce },
+            ciphertext: HexString {
+                bytes: value.ciphertext,
+            },
+        }
+    }
+}
+
+#[test]
+fn test_encrypted_key() {
+    let key = Key::random().unwrap();
+    let encrypted = EncryptedKey::encrypt(&key, "correct horse").unwrap();
+    assert_eq!(encrypted.owner, key.owner);
+
+    let serialized = serde_json::to_string(&encrypted).unwrap();
+    let deserialized: EncryptedKey = serde_json::from_str(&serialized).unwrap();
+    let decrypted = deserialized.decrypt("correct horse").unwrap();
+    assert_eq!(decrypted.owner, key.owner);
+    assert_eq!(
+        SerializedKey::from(decrypted).pair.bytes,
+        SerializedKey::from(key.clone()).pair.bytes
+    );
+
+    assert!(matches!(
+        deserialized.decrypt("wrong horse"),
+        Err(KeystoreError::WrongPassphrase)
+    ));
+    // The owner is authenticated with the key pair
+    let other = Key::random().unwrap();
+    let tampered = EncryptedKey {
+        owner: other.owner,
+        ..deserialized
+    };
+    assert!(matches!(
+        tampered.decrypt("correct horse"),
+        Err(KeystoreError::WrongPassphrase)
+    ));
+    // Parameters which would make the key derivation too costly are refused before it runs
+    let costly = EncryptedKey {
+        kdf: KdfParams {
+            m_cost: u32::MAX,
+            ..KdfParams::default()
+        },
+        ..tampered
+    };
+    assert!(matches!(
+        costly.decrypt("correct horse"),
+        Err(KeystoreError::InvalidKdfParams)
+    ));
+}
@@ -0,0 +1,25 @@
+//! UTXO model whose state transitions are proven with MidenVM.
+//!
+//! `utxo` holds the Rust implementation of the model, `prove` and `verify` run the same
+//! state transitions in Miden Assembly (with `UtxoAdvice` supplying the non-public data).
+
+pub mod advice_provider;
+pub mod genesis;
+pub mod keystore;
+pub mod mempool;
+pub mod prove;
+pub mod storage;
+pub mod utils;
+pub mod utxo;
+pub mod verify;
+pub mod wallet;
+
+#[cfg(test)]
+mod fuzz_tests;
+#[cfg(test)]
+mod masm_tests;
+
+pub use advice_provider::UtxoAdvice;
+pub use miden::Program;
+pub use miden_crypto::{dsa::rpo_falcon512::KeyPair, Felt, Word};
+pub use utxo::{Batch, Key, Output, SignedTransaction, State, Transaction, Utxo};
@@ -0,0 +1,1110 @@
+//! Module for writing tests for masm programs.
+
+use miden::{
+    math::Felt, Assembler, DefaultHost, ExecutionError, ExecutionTrace, MemAdviceProvider,
+    ProgramAst, ProvingOptions, StackInputs, Word,
+};
+use miden_core::{StarkField, WORD_SIZE};
+use miden_crypto::dsa::rpo_falcon512::KeyPair;
+use miden_processor::AdviceProvider;
+use miden_stdlib::StdLibrary;
+use std::{collections::BTreeMap, fmt::Write, str::FromStr};
+
+use crate::{
+    advice_provider::UtxoAdvice,
+    prove,
+    utils::HexString,
+    utxo::{
+        Batch, BatchError, InputSignature, Key, Mint, MintPolicy, Multisig, Output,
+        SerializedOutput, SerializedTransaction, SignedTransaction, Signer, State, StateError,
+        Transaction, TransactionError, Utxo, MAX_HEIGHT, MAX_SUPPLY, NATIVE_ASSET,
+    },
+};
+
+// Tries running the prover on a real UTXO state and transaction
+#[test]
+fn test_main() {
+    let key = Key::random().unwrap();
+    let owner = key.owner;
+    let initial_utxo = Utxo {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut initial_state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    initial_state.insert(initial_utxo.clone()).unwrap();
+
+    let output_1 = Output {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(10),
+        unlock_height: None,
+    };
+    let output_2 = Output {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(90),
+        unlock_height: None,
+    };
+
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![output_1, output_2],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    assert_vm_matches_rust(&mut initial_state, transaction, &[key.pair]);
+}
+
+// Merges UTXOs owned by different keys into a single one
+#[test]
+fn test_main_multiple_inputs() {
+    let key_1 = Key::random().unwrap();
+    let key_2 = Key::random().unwrap();
+    let utxo_1 = Utxo {
+        owner: key_1.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let utxo_2 = Utxo {
+        owner: key_2.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(50),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut initial_state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    initial_state.insert(utxo_1.clone()).unwrap();
+    initial_state.insert(utxo_2.clone()).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![utxo_2.hash(), utxo_1.hash()],
+        outputs: vec![Output {
+            owner: key_1.owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(140),
+            unlock_height: None,
+        }],
+        fee: Felt::new(10),
+        mint: None,
+    };
+    assert_vm_matches_rust(&mut initial_state, transaction, &[key_2.pair, key_1.pair]);
+}
+
+// Same as `test_main`, but with a shallow state tree and paying a fee
+#[test]
+fn test_main_small_tree() {
+    let key = Key::random().unwrap();
+    let owner = key.owner;
+    let initial_utxo = Utxo {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut initial_state = State::empty(4).unwrap();
+    initial_state.insert(initial_utxo.clone()).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![Output {
+            owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(99),
+            unlock_height: None,
+        }],
+        fee: Felt::new(1),
+        mint: None,
+    };
+    assert_vm_matches_rust(&mut initial_state, transaction, &[key.pair]);
+}
+
+// Applies two transactions in one batch, the second one spending an output of the first
+#[test]
+fn test_main_batch() {
+    let key_1 = Key::random().unwrap();
+    let key_2 = Key::random().unwrap();
+    let initial_utxo = Utxo {
+        owner: key_1.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut initial_state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    initial_state.insert(initial_utxo.clone()).unwrap();
+
+    let transaction_1 = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![
+            Output {
+                owner: key_2.owner,
+                asset_id: NATIVE_ASSET,
+                value: Felt::new(60),
+                unlock_height: None,
+            },
+            Output {
+                owner: key_1.owner,
+                asset_id: NATIVE_ASSET,
+                value: Felt::new(38),
+                unlock_height: None,
+            },
+        ],
+        fee: Felt::new(2),
+        mint: None,
+    };
+    let intermediate_utxo = transaction_1.output_utxos(Word::default()).remove(0);
+    let transaction_2 = Transaction {
+        inputs: vec![intermediate_utxo.hash()],
+        outputs: vec![Output {
+            owner: key_1.owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(57),
+            unlock_height: None,
+        }],
+        fee: Felt::new(3),
+        mint: None,
+    };
+    let batch = Batch {
+        transactions: vec![
+            SignedTransaction::new(transaction_1, &[key_1.pair]).unwrap(),
+            SignedTransaction::new(transaction_2, &[key_2.pair]).unwrap(),
+        ],
+    };
+    assert_batch_vm_matches_rust(&mut initial_state, batch);
+}
+
+// Creates two outputs with the same owner and value, which are distinct UTXOs
+// because of their serials
+#[test]
+fn test_main_identical_outputs() {
+    let key = Key::random().unwrap();
+    let owner = key.owner;
+    let initial_utxo = Utxo {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut initial_state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    initial_state.insert(initial_utxo.clone()).unwrap();
+
+    let output = Output {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(50),
+        unlock_height: None,
+    };
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![output.clone(), output],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    let utxos = transaction.output_utxos(Word::default());
+    assert_ne!(utxos[0].hash(), utxos[1].hash());
+    assert_vm_matches_rust(&mut initial_state, transaction, &[key.pair]);
+}
+
+// A UTXO put back in the state tree after being spent cannot be spent again
+#[test]
+fn test_main_already_spent() {
+    let key = Key::random().unwrap();
+    let owner = key.owner;
+    let initial_utxo = Utxo {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state.insert(initial_utxo.clone()).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![Output {
+            owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(100),
+            unlock_height: None,
+        }],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    let batch: Batch = SignedTransaction::new(transaction, &[key.pair])
+        .unwrap()
+        .into();
+    state.process_batch(batch.clone(), Word::default()).unwrap();
+    // `State::insert` refuses the leaf of a spent UTXO, so the UTXO is put back directly
+    let index = state.leaf_index(&initial_utxo.hash());
+    state.tree.update_leaf(index, initial_utxo.hash()).unwrap();
+    state.utxos.insert(index, initial_utxo);
+
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut state, batch),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::AlreadySpent,
+        }
+    ));
+}
+
+// An output cannot be stored at the leaf of the input the transaction spends, which holds the
+// nullifier of the input
+#[test]
+fn test_main_nullified_leaf() {
+    let key = Key::random().unwrap();
+    let owner = key.owner;
+    let initial_utxo = Utxo {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(2).unwrap();
+    state.insert(initial_utxo.clone()).unwrap();
+    let index = state.leaf_index(&initial_utxo.hash());
+
+    let transaction = (1..)
+        .map(|unlock_height| Transaction {
+            inputs: vec![initial_utxo.hash()],
+            outputs: vec![Output {
+                owner,
+                asset_id: NATIVE_ASSET,
+                value: Felt::new(100),
+                unlock_height: Some(unlock_height),
+            }],
+            fee: Felt::new(0),
+            mint: None,
+        })
+        .find(|tx| state.leaf_index(&tx.output_utxos(Word::default())[0].hash()) == index)
+        .unwrap();
+    let batch: Batch = SignedTransaction::new(transaction, &[key.pair])
+        .unwrap()
+        .into();
+
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut state, batch),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::LeafOccupied,
+        }
+    ));
+}
+
+// A time-locked UTXO can only be spent from its unlock height, and outputs can be locked too
+#[test]
+fn test_main_unlock_height() {
+    let key = Key::random().unwrap();
+    let owner = key.owner;
+    let initial_utxo = Utxo {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: Some(10),
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state.insert(initial_utxo.clone()).unwrap();
+    state.set_height(9).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![Output {
+            owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(100),
+            unlock_height: Some(20),
+        }],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    let batch: Batch = SignedTransaction::new(transaction, &[key.pair])
+        .unwrap()
+        .into();
+
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut state, batch.clone()),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::InvalidTransaction(TransactionError::LockedInput),
+        }
+    ));
+    // Inputs are checked before the values, so overspending a locked input fails the same way
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![Output {
+            owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(200),
+            unlock_height: None,
+        }],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(
+            &mut state,
+            SignedTransaction::new(transaction, &[key.pair])
+                .unwrap()
+                .into()
+        ),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::InvalidTransaction(TransactionError::LockedInput),
+        }
+    ));
+
+    state.set_height(10).unwrap();
+    // Unlock heights must compare the same as field elements and as integers
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![Output {
+            owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(100),
+            unlock_height: Some(MAX_HEIGHT + 1),
+        }],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(
+            &mut state,
+            SignedTransaction::new(transaction, &[key.pair])
+                .unwrap()
+                .into()
+        ),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::InvalidTransaction(TransactionError::HeightOverflow),
+        }
+    ));
+
+    // So must the height of the state (which `State::set_height` cannot go above)
+    let mut invalid_state = state.clone();
+    invalid_state.height = MAX_HEIGHT + 1;
+    let result = run_test(
+        "../masm/utxo.masm",
+        "main",
+        invalid_state.tree_depth(),
+        prove::prepare_stack_inputs(&invalid_state, &batch, Word::default()),
+        UtxoAdvice::new(&invalid_state, batch.clone(), Word::default()),
+        BTreeMap::default(),
+    );
+    assert!(matches!(
+        result.err().unwrap().downcast::<ExecutionError>().unwrap(),
+        ExecutionError::FailedAssertion(_, prove::err_code::INVALID_HEIGHT)
+    ));
+
+    assert_batch_vm_matches_rust(&mut state, batch);
+}
+
+// Spends a UTXO owned by a 2-of-3 multisig together with a UTXO owned by a single key
+#[test]
+fn test_main_multisig() {
+    let keys = [
+        Key::random().unwrap(),
+        Key::random().unwrap(),
+        Key::random().unwrap(),
+    ];
+    let multisig = Multisig::new(2, keys.iter().map(|key| key.owner).collect()).unwrap();
+    let multisig_utxo = Utxo {
+        owner: multisig.owner(),
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let single_utxo = Utxo {
+        owner: keys[1].owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(50),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut initial_state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    initial_state.insert(multisig_utxo.clone()).unwrap();
+    initial_state.insert(single_utxo.clone()).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![multisig_utxo.hash(), single_utxo.hash()],
+        outputs: vec![Output {
+            owner: keys[1].owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(150),
+            unlock_height: None,
+        }],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    let multisig_keys = [keys[2].pair, keys[0].pair];
+    let signers = [
+        Signer::Multisig {
+            owner: &multisig,
+            keys: &multisig_keys,
+        },
+        Signer::Key(&keys[1].pair),
+    ];
+    let signed_tx = SignedTransaction::with_signers(transaction, &signers).unwrap();
+    assert_batch_vm_matches_rust(&mut initial_state, signed_tx.into());
+}
+
+// A key given twice in a key set cannot sign twice to meet the threshold
+#[test]
+fn test_main_multisig_duplicate_key() {
+    let keys = [Key::random().unwrap(), Key::random().unwrap()];
+    // `Multisig::new` refuses the key set, but the program must refuse it too
+    let multisig = Multisig {
+        threshold: 2,
+        keys: vec![keys[0].owner, keys[0].owner, keys[1].owner],
+    };
+    let utxo = Utxo {
+        owner: multisig.owner(),
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state.insert(utxo.clone()).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![utxo.hash()],
+        outputs: vec![Output {
+            owner: keys[1].owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(100),
+            unlock_height: None,
+        }],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    let signature = keys[0].pair.sign(transaction.hash()).unwrap();
+    let signed_tx = SignedTransaction {
+        transaction,
+        signatures: vec![InputSignature::Multisig {
+            owner: multisig,
+            signatures: vec![(0, signature.clone()), (1, signature)],
+        }],
+    };
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut state, signed_tx.into()),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::InvalidTransaction(TransactionError::InvalidMultisig),
+        }
+    ));
+}
+
+// Spends a native and a custom asset UTXO, with outputs of both assets and a native fee
+#[test]
+fn test_main_multiple_assets() {
+    let key = Key::random().unwrap();
+    let asset_id = [Felt::new(7), Felt::new(8), Felt::new(9), Felt::new(10)];
+    let native_utxo = Utxo {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let asset_utxo = Utxo {
+        owner: key.owner,
+        asset_id,
+        value: Felt::new(50),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut initial_state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    initial_state.insert(native_utxo.clone()).unwrap();
+    initial_state.insert(asset_utxo.clone()).unwrap();
+
+    let output = |asset_id, value| Output {
+        owner: key.owner,
+        asset_id,
+        value: Felt::new(value),
+        unlock_height: None,
+    };
+    let transaction = Transaction {
+        inputs: vec![native_utxo.hash(), asset_utxo.hash()],
+        outputs: vec![
+            output(asset_id, 20),
+            output(NATIVE_ASSET, 90),
+            output(asset_id, 30),
+        ],
+        fee: Felt::new(10),
+        mint: None,
+    };
+    assert_vm_matches_rust(
+        &mut initial_state.clone(),
+        transaction.clone(),
+        &[key.pair, key.pair],
+    );
+
+    // Moving value from one asset to the other is rejected by both implementations
+    let transaction = Transaction {
+        outputs: vec![output(asset_id, 60), output(NATIVE_ASSET, 90)],
+        ..transaction
+    };
+    let batch: Batch = SignedTransaction::new(transaction, &[key.pair, key.pair])
+        .unwrap()
+        .into();
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut initial_state, batch),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::InvalidTransaction(TransactionError::ExcessiveOutput),
+        }
+    ));
+}
+
+// Mints a capped asset twice in a batch (the second mint also spends a native UTXO to pay
+// a fee), then checks minting above the cap is rejected by both implementations
+#[test]
+fn test_main_mint() {
+    let issuer = Key::random().unwrap();
+    let key = Key::random().unwrap();
+    let asset_id = [Felt::new(7), Felt::new(8), Felt::new(9), Felt::new(10)];
+    let native_utxo = Utxo {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state.insert(native_utxo.clone()).unwrap();
+    state
+        .insert_policy(MintPolicy::new(asset_id, issuer.owner, Some(100)))
+        .unwrap();
+
+    let output = |asset_id, value| Output {
+        owner: key.owner,
+        asset_id,
+        value: Felt::new(value),
+        unlock_height: None,
+    };
+    let mint = |value, minted| {
+        Some(Mint {
+            asset_id,
+            value: Felt::new(value),
+            minted,
+        })
+    };
+    let transaction_1 = Transaction {
+        inputs: Vec::new(),
+        outputs: vec![output(asset_id, 60)],
+        fee: Felt::new(0),
+        mint: mint(60, 0),
+    };
+    let transaction_2 = Transaction {
+        inputs: vec![native_utxo.hash()],
+        outputs: vec![output(NATIVE_ASSET, 90), output(asset_id, 30)],
+        fee: Felt::new(10),
+        mint: mint(30, 60),
+    };
+    let batch = Batch {
+        transactions: vec![
+            SignedTransaction::new(transaction_1, &[issuer.pair]).unwrap(),
+            SignedTransaction::new(transaction_2, &[key.pair, issuer.pair]).unwrap(),
+        ],
+    };
+    assert_batch_vm_matches_rust(&mut state, batch);
+    assert_eq!(state.get_policy(&asset_id).unwrap().minted, 90);
+
+    let transaction = Transaction {
+        inputs: Vec::new(),
+        outputs: vec![output(asset_id, 20)],
+        fee: Felt::new(0),
+        mint: mint(20, 90),
+    };
+    let batch: Batch = SignedTransaction::new(transaction, &[issuer.pair])
+        .unwrap()
+        .into();
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut state, batch),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::MintCapExceeded,
+        }
+    ));
+
+    // Minting an asset without a policy fails on the transaction minting it
+    let transaction_1 = Transaction {
+        inputs: Vec::new(),
+        outputs: vec![output(asset_id, 5)],
+        fee: Felt::new(0),
+        mint: mint(5, 90),
+    };
+    let unknown_asset = [Felt::new(11), Felt::new(12), Felt::new(13), Felt::new(14)];
+    let transaction_2 = Transaction {
+        inputs: Vec::new(),
+        outputs: vec![output(unknown_asset, 5)],
+        fee: Felt::new(0),
+        mint: Some(Mint {
+            asset_id: unknown_asset,
+            value: Felt::new(5),
+            minted: 0,
+        }),
+    };
+    let batch = Batch {
+        transactions: vec![
+            SignedTransaction::new(transaction_1, &[issuer.pair]).unwrap(),
+            SignedTransaction::new(transaction_2, &[issuer.pair]).unwrap(),
+        ],
+    };
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut state, batch),
+        BatchError::InvalidTransaction {
+            index: 1,
+            error: StateError::UnknownAsset,
+        }
+    ));
+
+    // Without a cap the minted total is still bounded by `MAX_SUPPLY`
+    let policy = MintPolicy {
+        minted: MAX_SUPPLY - 10,
+        ..MintPolicy::new(unknown_asset, issuer.owner, None)
+    };
+    state.insert_policy(policy).unwrap();
+    let transaction = Transaction {
+        inputs: Vec::new(),
+        outputs: vec![output(unknown_asset, 11)],
+        fee: Felt::new(0),
+        mint: Some(Mint {
+            asset_id: unknown_asset,
+            value: Felt::new(11),
+            minted: MAX_SUPPLY - 10,
+        }),
+    };
+    let batch: Batch = SignedTransaction::new(transaction, &[issuer.pair])
+        .unwrap()
+        .into();
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut state, batch),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::MintCapExceeded,
+        }
+    ));
+}
+
+// Outputs whose values add up to the input value modulo the field modulus must not be
+// able to create value
+#[test]
+fn test_main_value_overflow() {
+    let key = Key::random().unwrap();
+    let initial_utxo = Utxo {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state.insert(initial_utxo.clone()).unwrap();
+
+    let output = |value| Output {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(value),
+        unlock_height: None,
+    };
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![output(Felt::MODULUS - 50), output(150)],
+        fee: Felt::new(0),
+        mint: None,
+    };
+    let batch: Batch = SignedTransaction::new(transaction, &[key.pair])
+        .unwrap()
+        .into();
+    assert!(matches!(
+        assert_batch_vm_fails_like_rust(&mut state, batch),
+        BatchError::InvalidTransaction {
+            index: 0,
+            error: StateError::InvalidTransaction(TransactionError::ValueOverflow),
+        }
+    ));
+}
+
+// Runs the batch through MidenVM and the Rust implementation and checks both reject it with
+// the same error (translating the failure of the program with `prove::batch_error`).
+fn assert_batch_vm_fails_like_rust(state: &mut State, batch: Batch) -> BatchError {
+    let stack_inputs = prove::prepare_stack_inputs(state, &batch, Word::default());
+    let advice_provider = UtxoAdvice::new(state, batch.clone(), Word::default());
+    let current_transaction = advice_provider.current_transaction();
+    let result = run_test(
+        "../masm/utxo.masm",
+        "main",
+        state.tree_depth(),
+        stack_inputs,
+        advice_provider,
+        BTreeMap::default(),
+    );
+    let vm_error = result
+        .err()
+        .expect("The program accepted the batch")
+        .downcast::<ExecutionError>()
+        .unwrap();
+    let vm_error = prove::batch_error(&vm_error, current_transaction.get())
+        .unwrap_or_else(|| panic!("No matching error for {vm_error:?}"));
+
+    let error = state
+        .process_batch(batch, Word::default())
+        .err()
+        .expect("The Rust implementation accepted the batch");
+    assert_eq!(vm_error, error);
+    error
+}
+
+// Runs the transaction through MidenVM and the Rust implementation and checks the
+// resulting state and nullifier roots match.
+fn assert_vm_matches_rust(state: &mut State, transaction: Transaction, keys: &[KeyPair]) {
+    let signed_tx = SignedTransaction::new(transaction, keys).unwrap();
+    assert_batch_vm_matches_rust(state, signed_tx.into());
+}
+
+fn assert_batch_vm_matches_rust(state: &mut State, batch: Batch) {
+    let fee_collector = [Felt::new(1), Felt::new(2), Felt::new(3), Felt::new(4)];
+    let stack_inputs = prove::prepare_stack_inputs(state, &batch, fee_collector);
+    let advice_provider = UtxoAdvice::new(state, batch.clone(), fee_collector);
+
+    let trace = run_test(
+        "../masm/utxo.masm",
+        "main",
+        state.tree_depth(),
+        stack_inputs,
+        advice_provider,
+        BTreeMap::default(),
+    )
+    .unwrap();
+    // The top 4 elements on the stack represents the state root in reverse,
+    // followed by the nullifier root
+    let mut stack_outputs = trace.stack_outputs().stack()[0..4].to_vec();
+    stack_outputs.reverse();
+    let mut nullifier_outputs = trace.stack_outputs().stack()[4..8].to_vec();
+    nullifier_outputs.reverse();
+
+    // Re-run the batch in Rust implementation equivalent to compare the results
+    state.process_batch(batch, fee_collector).unwrap();
+    let state_root = state
+        .get_root()
+        .into_iter()
+        .map(|el| el.as_int())
+        .collect::<Vec<u64>>();
+    assert_eq!(state_root, stack_outputs);
+    let nullifier_root = state
+        .get_nullifier_root()
+        .into_iter()
+        .map(|el| el.as_int())
+        .collect::<Vec<u64>>();
+    assert_eq!(nullifier_root, nullifier_outputs);
+}
+
+// Every assertion of the program has its own error code, declared with the same value as
+// in `prove::err_code`
+#[test]
+fn test_error_codes() {
+    use prove::err_code::*;
+    let code = std::fs::read_to_string("../masm/utxo.masm").unwrap();
+    let constants: BTreeMap<&str, u64> = code
+        .lines()
+        .filter_map(|line| line.strip_prefix("const.")?.split_once('='))
+        .map(|(name, value)| (name, value.parse().unwrap()))
+        .collect();
+    let assertions: Vec<&str> = code
+        .lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| line.starts_with("assert"))
+        .collect();
+    let mut err_codes: Vec<u64> = assertions
+        .iter()
+        .map(|line| {
+            let name = line
+                .split_once(".err=")
+                .unwrap_or_else(|| panic!("No error code for `{line}`"))
+                .1;
+            constants[name]
+        })
+        .collect();
+    err_codes.sort();
+    err_codes.dedup();
+    assert_eq!(err_codes.len(), assertions.len());
+
+    for (name, value) in [
+        ("UNKNOWN_UTXO_HASH", UNKNOWN_UTXO_HASH),
+        ("INVALID_TRANSACTION_SIZE", INVALID_TRANSACTION_SIZE),
+        ("NO_INPUTS", NO_INPUTS),
+        ("LEAF_OCCUPIED", LEAF_OCCUPIED),
+        ("EXCESSIVE_OUTPUT", EXCESSIVE_OUTPUT),
+        ("UNSPENT_INPUT", UNSPENT_INPUT),
+        ("EMPTY_BATCH", EMPTY_BATCH),
+        ("ALREADY_SPENT", ALREADY_SPENT),
+        ("LOCKED_INPUT", LOCKED_INPUT),
+        ("INVALID_MULTISIG", INVALID_MULTISIG),
+        ("MISSING_SIGNATURE", MISSING_SIGNATURE),
+        ("INVALID_SIGNER", INVALID_SIGNER),
+        ("UNKNOWN_ASSET", UNKNOWN_ASSET),
+        ("EMPTY_MINT", EMPTY_MINT),
+        ("INVALID_MINTED_SUPPLY", INVALID_MINTED_SUPPLY),
+        ("MINT_CAP_EXCEEDED", MINT_CAP_EXCEEDED),
+        ("VALUE_OVERFLOW", VALUE_OVERFLOW),
+        ("INVALID_TRANSACTION_WORDS", INVALID_TRANSACTION_WORDS),
+        ("INVALID_TRANSACTION_DATA", INVALID_TRANSACTION_DATA),
+        ("INVALID_UTXO_DATA", INVALID_UTXO_DATA),
+        ("INVALID_MINT_POLICY", INVALID_MINT_POLICY),
+        ("INVALID_BATCH_DATA", INVALID_BATCH_DATA),
+        ("INVALID_HASH_RANGE", INVALID_HASH_RANGE),
+        ("UNKNOWN_SIGNER", UNKNOWN_SIGNER),
+        ("NULLIFIED_LEAF", NULLIFIED_LEAF),
+        ("HEIGHT_OVERFLOW", HEIGHT_OVERFLOW),
+        ("SUPPLY_OVERFLOW", SUPPLY_OVERFLOW),
+        ("DUPLICATE_KEY", DUPLICATE_KEY),
+        ("INVALID_HEIGHT", INVALID_HEIGHT),
+    ] {
+        assert_eq!(constants[name], u64::from(value), "{name}");
+    }
+}
+
+#[test]
+fn test_divmod() {
+    fn test_case(x: u32, y: u32) {
+        let expected_remainder = (x % y) as u64;
+        let expected_quotient = (x / y) as u64;
+
+        let inputs = StackInputs::try_from_values([x as u64, y as u64]).unwrap();
+        let trace = run_test(
+            "../masm/utxo.masm",
+            "divmod",
+            State::DEFAULT_TREE_DEPTH,
+            inputs,
+            MemAdviceProvider::default(),
+            BTreeMap::new(),
+        )
+        .unwrap();
+        let outputs = trace.stack_outputs();
+        assert_eq!(
+            &outputs.stack()[0..2],
+            &[expected_remainder, expected_quotient]
+        );
+        assert!(outputs.stack().iter().skip(2).all(|x| *x == 0));
+    }
+
+    test_case(1, 1);
+    test_case(1, 2);
+    test_case(1, 3);
+    test_case(2, 1);
+    test_case(3, 1);
+    test_case(5, 10);
+    test_case(10, 5);
+    test_case(101, 4);
+    test_case(103, 4);
+}
+
+#[test]
+fn test_range_hash() {
+    fn test_case(inputs: Vec<&str>, utxos: Vec<(&str, &str)>, fee: &str) {
+        let transaction = Transaction::try_from(SerializedTransaction {
+            inputs: inputs
+                .iter()
+                .map(|input| HexString::from_str(input).unwrap())
+                .collect(),
+            outputs: utxos
+                .iter()
+                .map(|(owner, value)| SerializedOutput {
+                    owner: HexString::from_str(owner).unwrap(),
+                    asset_id: None,
+                    value: HexString::from_str(value).unwrap(),
+                    unlock_height: None,
+                })
+                .collect(),
+            fee: HexString::from_str(fee).unwrap(),
+        })
+        .unwrap();
+
+        let hash = transaction
+            .hash()
+            .iter()
+            .map(|u| u.as_int())
+            .collect::<Vec<u64>>();
+
+        // The fee and input hashes take 4 elements each, outputs take 12 elements each
+        // (3 words).
+        let number_of_elements_to_hash = 4 + inputs.len() as u64 * 4 + utxos.len() as u64 * 12;
+        let stack_inputs = StackInputs::try_from_values([20, number_of_elements_to_hash]).unwrap();
+        // Fill in the memory with transaction field elements contiguously in chunks of WORD SIZE
+        let mut memory = BTreeMap::new();
+        memory.insert(
+            20,
+            vec![
+                transaction.fee.to_string(),
+                "0".into(),
+                "0".into(),
+                "0".into(),
+            ],
+        );
+        transaction.inputs.iter().for_each(|input| {
+            memory.insert(
+                20 + memory.len(),
+                input.iter().map(|i| i.to_string()).collect::<Vec<String>>(),
+            );
+        });
+        // Outputs each have 3 words: the owner, the value and unlock height (padded with
+        // zeros) and the asset id.
+        // Then we distribute these elements in incremental memory addresses.
+        transaction.outputs.iter().for_each(|output| {
+            let mut utxo_felts: Vec<String> = output
+                .owner
+                .iter()
+                .chain(std::iter::once(&output.value))
+                .map(|felt| felt.to_string())
+                .chain(std::iter::once(
+                    output.unlock_height.unwrap_or(0).to_string(),
+                ))
+                .collect();
+            while utxo_felts.len() % WORD_SIZE > 0 {
+                utxo_felts.push("0".into());
+            }
+            utxo_felts.extend(output.asset_id.iter().map(|felt| felt.to_string()));
+            utxo_felts.chunks(WORD_SIZE).for_each(|chunk| {
+                memory.insert(20 + memory.len(), chunk.into());
+            });
+        });
+
+        let trace = run_test(
+            "../masm/utxo.masm",
+            "range_hash",
+            State::DEFAULT_TREE_DEPTH,
+            stack_inputs,
+            MemAdviceProvider::default(),
+            memory,
+        )
+        .unwrap();
+
+        let mut outputs = trace.stack_outputs().stack().to_vec();
+        outputs.truncate(WORD_SIZE);
+        outputs.reverse(); // Stack is always returned in reverse
+        assert_eq!(outputs, hash);
+    }
+
+    // tx_1
+    test_case(
+        vec!["0xc039faf939fe7908f959dd5da871658e1b3f48998e9bd5165eb5acce45764fbb"],
+        vec![
+            (
+                "0xda51ad197710bafc3192226e859c8b29a2b1757dafcda157a0a293a8e392517c",
+                "0xf000000000000000",
+            ),
+            (
+                "0x496d5921189e0f6a49b64c90a62286a47381bd63641ef9d847f7b9fc917b68f8",
+                "0x0f00000000000000",
+            ),
+        ],
+        "0x0000000000000000",
+    );
+    // tx_2
+    test_case(
+        vec!["0x42007c73912db5a323312160d24008aacd6a25d38b3f30adb37dca7304e46347"],
+        vec![(
+            "0xda51ad197710bafc3192226e859c8b29a2b1757dafcda157a0a293a8e392517c",
+            "0x0f00000000000000",
+        )],
+        "0x0000000000000000",
+    );
+    // tx_3
+    test_case(
+        vec!["0xeca9699210de0ecf6764f1dde94410e142645796170ac016dc22dc6a3c84b1db"],
+        vec![],
+        "0x0f00000000000000",
+    );
+    // Multiple inputs
+    test_case(
+        vec![
+            "0xeca9699210de0ecf6764f1dde94410e142645796170ac016dc22dc6a3c84b1db",
+            "0x42007c73912db5a323312160d24008aacd6a25d38b3f30adb37dca7304e46347",
+        ],
+        vec![(
+            "0xda51ad197710bafc3192226e859c8b29a2b1757dafcda157a0a293a8e392517c",
+            "0xf000000000000000",
+        )],
+        "0x0f00000000000000",
+    );
+}
+
+fn run_test<A: AdviceProvider>(
+    masm_source_path: &str,
+    proc_name: &str,
+    tree_depth: u8,
+    stack_inputs: StackInputs,
+    advice_provider: A,
+    memory: BTreeMap<usize, Vec<String>>,
+) -> anyhow::Result<ExecutionTrace> {
+    let code = prove::set_tree_depth(&std::fs::read_to_string(masm_source_path)?, tree_depth);
+    let ast = ProgramAst::parse(&code)?;
+
+    let main_start = ast
+        .body()
+        .source_locations()
+        .first()
+        .ok_or_else(|| anyhow::Error::msg("No start source location"))?
+        .line() as usize
+        - 2;
+    let main_end = ast
+        .body()
+        .source_locations()
+        .last()
+        .ok_or_else(|| anyhow::Error::msg("No end source location"))?
+        .line() as usize;
+
+    // Simple masm code to run the procedure we want.
+    let memory_code = memory
+        .iter()
+        .map(|(index, elements)| {
+            assert_eq!(elements.len(), WORD_SIZE);
+            format!(
+                "  push.{}\n  mem_storew.{index}\n  dropw\n",
+                elements.join(".")
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("");
+
+    let test_harness = format!("begin\n{memory_code}\n  exec.{proc_name}\nend");
+    let test_code = code
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            if (main_start..=main_end).contains(&i) {
+                None
+            } else {
+                Some(line)
+            }
+        })
+        .chain(test_harness.lines())
+        .fold(String::new(), |mut acc, l| {
+            writeln!(&mut acc, "{l}").unwrap();
+            acc
+        });
+
+    let assembler = Assembler::default().with_library(&StdLibrary::default())?;
+    let program = assembler.compile(test_code)?;
+    let host = DefaultHost::new(advice_provider);
+    let trace = miden::execute(
+        &program,
+        stack_inputs,
+        host,
+        *ProvingOptions::default().execution_options(),
+    )?;
+
+    Ok(trace)
+}
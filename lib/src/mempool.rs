@@ -0,0 +1,417 @@
+//! Pool of signed transactions waiting to be included in a batch.
+//!
+//! Every pending transaction is valid against the current state, its outputs land on leaves
+//! which are free in the state, and no two pending transactions spend the same input (or mint
+//! the same asset) or have outputs landing on the same leaf, so any selection of them can be
+//! applied together.
+
+use crate::{
+    advice_provider::raw_word,
+    utxo::{Batch, MintPolicy, SignedTransaction, State, StateError, TransactionError},
+};
+use miden_crypto::Word;
+use std::{collections::BTreeMap, fmt};
+
+/// Order in which pending transactions are picked for the next batch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderingPolicy {
+    /// Oldest transactions first.
+    #[default]
+    Fifo,
+    /// Transactions paying the highest fee first (oldest first among equal fees).
+    HighestFee,
+}
+
+#[derive(Debug, Default)]
+pub struct Mempool {
+    /// Pending transactions keyed by their arrival number.
+    transactions: BTreeMap<u64, SignedTransaction>,
+    /// Arrival number of each pending transaction, keyed by transaction hash.
+    by_hash: BTreeMap<[u64; 4], u64>,
+    /// Arrival number of the pending transaction spending each input. Mints use the key of
+    /// the policy of their asset (see `MintPolicy::key`) as an input, since the policy changes
+    /// with every mint.
+    spent_inputs: BTreeMap<[u64; 4], u64>,
+    /// Arrival number of the pending transaction with an output (or fee UTXO) at each leaf of
+    /// the state tree.
+    output_leaves: BTreeMap<u64, u64>,
+    /// Owner of the UTXOs crediting the fees, which the leaves of fee UTXOs depend on.
+    fee_collector: Word,
+    next_number: u64,
+}
+
+impl Mempool {
+    pub fn new(fee_collector: Word) -> Self {
+        Self {
+            fee_collector,
+            ..Self::default()
+        }
+    }
+
+    pub fn contains(&self, transaction_hash: Word) -> bool {
+        self.by_hash.contains_key(&raw_word(transaction_hash))
+    }
+
+    /// Add a transaction after checking it against the current state and the other
+    /// pending transactions. Returns the hash of the transaction.
+    pub fn add(
+        &mut self,
+        state: &State,
+        transaction: SignedTransaction,
+    ) -> Result<Word, MempoolError> {
+        let tx = &transaction.transaction;
+        let hash = tx.hash();
+        if self.contains(hash) {
+            return Err(MempoolError::AlreadyPending);
+        }
+
+        let mut inputs = Vec::with_capacity(tx.inputs.len());
+        for (i, input) in tx.inputs.iter().enumerate() {
+            if self.spent_inputs.contains_key(&raw_word(*input)) {
+                return Err(MempoolError::ConflictingInput);
+            }
+            // Spending the same UTXO twice is the same as spending an unknown one
+            if tx.inputs[..i].contains(input) {
+                return Err(MempoolError::UnknownUtxoHash);
+            }
+            let utxo = state.get_utxo(input).ok_or(MempoolError::UnknownUtxoHash)?;
+            inputs.push(utxo.clone());
+        }
+        let issuer = match &tx.mint {
+            Some(mint) => {
+                if self
+                    .spent_inputs
+                    .contains_key(&raw_word(MintPolicy::key(mint.asset_id)))
+                {
+                    return Err(MempoolError::ConflictingInput);
+                }
+                let policy = state.check_mint(mint).map_err(MempoolError::InvalidMint)?;
+                Some(policy.issuer)
+            }
+            None => None,
+        };
+        transaction.verify(&inputs, issuer, state.height)?;
+        // An output landing on an occupied leaf would make any batch with the transaction fail
+        let mut output_leaves = Vec::with_capacity(tx.outputs.len() + 1);
+        for utxo in tx.output_utxos(self.fee_collector) {
+            let index = state.leaf_index(&utxo.hash());
+            if state.is_occupied(index)
+                || output_leaves.contains(&index)
+                || self.output_leaves.contains_key(&index)
+            {
+                return Err(MempoolError::LeafOccupied);
+            }
+            output_leaves.push(index);
+        }
+
+        let number = self.next_number;
+        self.next_number += 1;
+        for input in used_inputs(&transaction) {
+            self.spent_inputs.insert(raw_word(input), number);
+        }
+        for index in output_leaves {
+            self.output_leaves.insert(index, number);
+        }
+        self.by_hash.insert(raw_word(hash), number);
+        self.transactions.insert(number, transaction);
+        Ok(hash)
+    }
+
+    /// Remove a pending transaction, returning it if it was in the pool.
+    pub fn remove(&mut self, transaction_hash: Word) -> Option<SignedTransaction> {
+        let number = self.by_hash.remove(&raw_word(transaction_hash))?;
+        let transaction = self.transactions.remove(&number)?;
+        for input in used_inputs(&transaction) {
+            self.spent_inputs.remove(&raw_word(input));
+        }
+        self.output_leaves.retain(|_, pending| *pending != number);
+        Some(transaction)
+    }
+
+    /// Pending transactions in the order given by the policy.
+    pub fn ordered(&self, policy: OrderingPolicy) -> Vec<&SignedTransaction> {
+        // The map is iterated in arrival order, and the sort below is stable
+        let mut transactions: Vec<&SignedTransaction> = self.transactions.values().collect();
+        if policy == OrderingPolicy::HighestFee {
+            transactions.sort_by_key(|tx| std::cmp::Reverse(tx.transaction.fee.as_int()));
+        }
+        transactions
+    }
+
+    /// Build a batch from (at most `max_transactions` of) the pending transactions.
+    /// The transactions stay in the pool until they are evicted by `evict_invalid`
+    /// once the batch is applied. Returns `None` if the pool is empty.
+    pub fn next_batch(&self, policy: OrderingPolicy, max_transactions: usize) -> Option<Batch> {
+        let transactions: Vec<SignedTransaction> = self
+            .ordered(policy)
+            .into_iter()
+            .take(max_transactions)
+            .cloned()
+            .collect();
+        if transactions.is_empty() {
+            return None;
+        }
+        Some(Batch { transactions })
+    }
+
+    /// Remove the transactions whose inputs are no longer in the state, for example
+    /// because they were spent by a newly applied batch, whose mint no longer matches
+    /// the policy of its asset, or with an output landing on a leaf which is now occupied.
+    /// Returns the removed transactions.
+    pub fn evict_invalid(&mut self, state: &State) -> Vec<SignedTransaction> {
+        let invalid: Vec<Word> = self
+            .transactions
+            .values()
+            .filter(|tx| {
+                let tx = &tx.transaction;
+                tx.inputs
+                    .iter()
+                    .any(|input| state.get_utxo(input).is_none())
+                    || tx
+                        .mint
+                        .as_ref()
+                        .is_some_and(|mint| state.check_mint(mint).is_err())
+                    || tx
+                        .output_utxos(self.fee_collector)
+                        .iter()
+                        .any(|utxo| state.is_occupied(state.leaf_index(&utxo.hash())))
+            })
+            .map(|tx| tx.transaction.hash())
+            .collect();
+        invalid
+            .into_iter()
+            .filter_map(|hash| self.remove(hash))
+            .collect()
+    }
+}
+
+/// The inputs of the transaction, followed by the policy key of the asset it mints (if any).
+fn used_inputs(transaction: &SignedTransaction) -> impl Iterator<Item = Word> + '_ {
+    let tx = &transaction.transaction;
+    let mint = tx.mint.as_ref().map(|mint| MintPolicy::key(mint.asset_id));
+    tx.inputs.iter().copied().chain(mint)
+}
+
+#[derive(Debug)]
+pub enum MempoolError {
+    AlreadyPending,
+    /// An input of the transaction is already spent by another pending transaction.
+    ConflictingInput,
+    UnknownUtxoHash,
+    /// An output of the transaction lands on a leaf which is occupied in the state, or by an
+    /// output of another pending transaction.
+    LeafOccupied,
+    /// The mint of the transaction does not match the minting policy of its asset.
+    InvalidMint(StateError),
+    InvalidTransaction(TransactionError),
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+impl From<TransactionError> for MempoolError {
+    fn from(value: TransactionError) -> Self {
+        Self::InvalidTransaction(value)
+    }
+}
+
+#[test]
+fn test_mempool() {
+    use crate::utxo::{Key, Output, Transaction, Utxo, NATIVE_ASSET};
+    use miden_crypto::Felt;
+
+    let key = Key::random().unwrap();
+    let utxos: Vec<Utxo> = (1..=3)
+        .map(|value| Utxo {
+            owner: key.owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(100 * value),
+            serial: Word::default(),
+            unlock_height: None,
+        })
+        .collect();
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    for utxo in utxos.iter() {
+        state.insert(utxo.clone()).unwrap();
+    }
+    let spend = |input: &Utxo, fee: u64| {
+        let transaction = Transaction {
+            inputs: vec![input.hash()],
+            outputs: vec![Output {
+                owner: key.owner,
+                asset_id: NATIVE_ASSET,
+                value: input.value - Felt::new(fee),
+                unlock_height: None,
+            }],
+            fee: Felt::new(fee),
+            mint: None,
+        };
+        SignedTransaction::new(transaction, &[key.pair.clone()]).unwrap()
+    };
+
+    let mut mempool = Mempool::new(Word::default());
+    let low_fee = mempool.add(&state, spend(&utxos[0], 1)).unwrap();
+    let high_fee = mempool.add(&state, spend(&utxos[1], 5)).unwrap();
+    assert!(matches!(
+        mempool.add(&state, spend(&utxos[1], 5)),
+        Err(MempoolError::AlreadyPending)
+    ));
+    assert!(matches!(
+        mempool.add(&state, spend(&utxos[0], 2)),
+        Err(MempoolError::ConflictingInput)
+    ));
+    let mut invalid = spend(&utxos[2], 1);
+    invalid.transaction.fee = Felt::new(2);
+    assert!(matches!(
+        mempool.add(&state, invalid),
+        Err(MempoolError::InvalidTransaction(_))
+    ));
+
+    let hashes = |batch: Batch| -> Vec<Word> {
+        batch
+            .transactions
+            .iter()
+            .map(|tx| tx.transaction.hash())
+            .collect()
+    };
+    let fifo = mempool.next_batch(OrderingPolicy::Fifo, 10).unwrap();
+    assert_eq!(hashes(fifo), vec![low_fee, high_fee]);
+    let highest_fee = mempool.next_batch(OrderingPolicy::HighestFee, 1).unwrap();
+    assert_eq!(hashes(highest_fee.clone()), vec![high_fee]);
+
+    // Applying the batch evicts its transaction
+    state.process_batch(highest_fee, Word::default()).unwrap();
+    let evicted = mempool.evict_invalid(&state);
+    assert_eq!(evicted.len(), 1);
+    assert!(!mempool.contains(high_fee));
+    assert!(mempool.contains(low_fee));
+    assert_eq!(mempool.ordered(OrderingPolicy::Fifo).len(), 1);
+}
+
+#[test]
+fn test_mempool_mint() {
+    use crate::utxo::{Key, Mint, Output, Transaction};
+    use miden_crypto::Felt;
+
+    let issuer = Key::random().unwrap();
+    let asset_id = [Felt::new(7), Felt::new(8), Felt::new(9), Felt::new(10)];
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state
+        .insert_policy(MintPolicy::new(asset_id, issuer.owner, None))
+        .unwrap();
+    let mint = |value: u64| {
+        let transaction = Transaction {
+            inputs: Vec::new(),
+            outputs: vec![Output {
+                owner: issuer.owner,
+                asset_id,
+                value: Felt::new(value),
+                unlock_height: None,
+            }],
+            fee: Felt::new(0),
+            mint: Some(Mint {
+                asset_id,
+                value: Felt::new(value),
+                minted: 0,
+            }),
+        };
+        SignedTransaction::new(transaction, &[issuer.pair]).unwrap()
+    };
+
+    // Only one mint of an asset can be pending, since each mint changes its policy
+    let mut mempool = Mempool::new(Word::default());
+    let hash = mempool.add(&state, mint(10)).unwrap();
+    assert!(matches!(
+        mempool.add(&state, mint(20)),
+        Err(MempoolError::ConflictingInput)
+    ));
+
+    let batch = mempool.next_batch(OrderingPolicy::Fifo, 10).unwrap();
+    state.process_batch(batch, Word::default()).unwrap();
+    assert_eq!(mempool.evict_invalid(&state).len(), 1);
+    assert!(!mempool.contains(hash));
+    assert!(matches!(
+        mempool.add(&state, mint(20)),
+        Err(MempoolError::InvalidMint(StateError::InvalidMintedSupply))
+    ));
+}
+
+#[test]
+fn test_mempool_output_leaves() {
+    use crate::utxo::{Key, Output, Transaction, Utxo, NATIVE_ASSET};
+    use miden_crypto::Felt;
+
+    // In a tree with 8 leaves, outputs often land on occupied leaves
+    let key = Key::random().unwrap();
+    let mut state = State::empty(3).unwrap();
+    let mut utxos = Vec::new();
+    for serial in 0.. {
+        let utxo = Utxo {
+            owner: key.owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(100),
+            serial: [Felt::new(serial), Felt::new(0), Felt::new(0), Felt::new(0)],
+            unlock_height: None,
+        };
+        if state.insert(utxo.clone()).is_ok() {
+            utxos.push(utxo);
+        }
+        if utxos.len() == 2 {
+            break;
+        }
+    }
+    // Spending of the input to the given owner, whose output is at the leaf returned
+    let spend = |input: &Utxo, owner: u64| {
+        let transaction = Transaction {
+            inputs: vec![input.hash()],
+            outputs: vec![Output {
+                owner: [Felt::new(owner), Felt::new(0), Felt::new(0), Felt::new(0)],
+                asset_id: NATIVE_ASSET,
+                value: input.value,
+                unlock_height: None,
+            }],
+            fee: Felt::new(0),
+            mint: None,
+        };
+        let leaf = state.leaf_index(&transaction.output_utxos(Word::default())[0].hash());
+        (transaction, leaf)
+    };
+    let sign = |transaction| SignedTransaction::new(transaction, &[key.pair]).unwrap();
+
+    let mut mempool = Mempool::new(Word::default());
+    let (first, first_leaf) = (0..)
+        .map(|owner| spend(&utxos[0], owner))
+        .find(|(_, leaf)| !state.is_occupied(*leaf))
+        .unwrap();
+    let first = mempool.add(&state, sign(first)).unwrap();
+
+    // Another output cannot land on the same leaf while the first transaction is pending
+    let (second, _) = (0..)
+        .map(|owner| spend(&utxos[1], owner))
+        .find(|(_, leaf)| *leaf == first_leaf)
+        .unwrap();
+    let second = sign(second);
+    assert!(matches!(
+        mempool.add(&state, second.clone()),
+        Err(MempoolError::LeafOccupied)
+    ));
+    // Nor on the leaf of a UTXO of the state
+    let occupied = state.leaf_index(&utxos[0].hash());
+    let (third, _) = (0..)
+        .map(|owner| spend(&utxos[1], owner))
+        .find(|(_, leaf)| *leaf == occupied)
+        .unwrap();
+    assert!(matches!(
+        mempool.add(&state, sign(third)),
+        Err(MempoolError::LeafOccupied)
+    ));
+
+    mempool.remove(first);
+    mempool.add(&state, second).unwrap();
+}
@@ -0,0 +1,350 @@
+use crate::{
+    advice_provider::UtxoAdvice,
+    utils::{self, HexString},
+    utxo::{leaf_index_mask, Batch, BatchError, State, StateError, TransactionError},
+};
+use anyhow::Context;
+use miden::{
+    math::Felt, Assembler, DefaultHost, ExecutionError, ExecutionProof, Program, ProvingError,
+    ProvingOptions, StackInputs, StackOutputs, Word,
+};
+use miden_stdlib::StdLibrary;
+use std::{fmt::Write, path::Path};
+
+#[derive(Debug, Clone)]
+pub struct ProveOutput {
+    pub program_hash: [u8; 32],
+    pub stack_outputs: StackOutputs,
+    pub proof: ExecutionProof,
+}
+
+/// Result of executing the program without proving it (see `run`).
+#[derive(Debug, Clone)]
+pub struct RunOutput {
+    pub stack_outputs: StackOutputs,
+    /// Number of VM cycles the execution took.
+    pub cycles: usize,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedProveOutput {
+    pub program_hash: HexString,
+    pub stack_outputs: Vec<String>,
+    pub overflow_addrs: Vec<String>,
+    /// Hex encoded proof (without `0x` prefix).
+    pub proof: String,
+}
+
+/// Execute the program on the batch of transactions (applied to `state`, with fees
+/// credited to `fee_collector`) and prove the execution.
+/// The program must have been compiled for the tree depth of the state.
+/// If the program fails on an invalid batch, the error is the `BatchError` the Rust
+/// implementation gives for it (see `batch_error`).
+pub fn prove(
+    program: &Program,
+    state: &State,
+    batch: Batch,
+    fee_collector: Word,
+) -> anyhow::Result<ProveOutput> {
+    let stack_inputs = prepare_stack_inputs(state, &batch, fee_collector);
+    let advice_provider = UtxoAdvice::new(state, batch, fee_collector);
+    let current_transaction = advice_provider.current_transaction();
+    let host = DefaultHost::new(advice_provider);
+
+    let (stack_outputs, proof) =
+        miden::prove(program, stack_inputs, host, ProvingOptions::default()).map_err(|error| {
+            match error {
+                ProvingError::ExecutionError(error) => {
+                    execution_error(error, current_transaction.get())
+                }
+                error => error.into(),
+            }
+        })?;
+
+    Ok(ProveOutput {
+        program_hash: program.hash().into(),
+        stack_outputs,
+        proof,
+    })
+}
+
+/// Execute the program on the batch of transactions like `prove` does, without proving
+/// the execution. This is much faster, so it can be used to check a batch would be accepted.
+pub fn run(
+    program: &Program,
+    state: &State,
+    batch: Batch,
+    fee_collector: Word,
+) -> anyhow::Result<RunOutput> {
+    let stack_inputs = prepare_stack_inputs(state, &batch, fee_collector);
+    let advice_provider = UtxoAdvice::new(state, batch, fee_collector);
+    let current_transaction = advice_provider.current_transaction();
+    let host = DefaultHost::new(advice_provider);
+
+    let trace = miden::execute(
+        program,
+        stack_inputs,
+        host,
+        *ProvingOptions::default().execution_options(),
+    )
+    .map_err(|error| execution_error(error, current_transaction.get()))?;
+
+    Ok(RunOutput {
+        stack_outputs: trace.stack_outputs().clone(),
+        cycles: trace.trace_len_summary().main_trace_len(),
+    })
+}
+
+/// Convert a failure of the program into the `BatchError` the Rust implementation gives for
+/// the batch if there is one, and into a plain error otherwise.
+pub fn execution_error(error: ExecutionError, current_transaction: Option<usize>) -> anyhow::Error {
+    match batch_error(&error, current_transaction) {
+        Some(batch_error) => batch_error.into(),
+        None => error.into(),
+    }
+}
+
+/// Translate a failure of the program into the error `State::process_batch` gives for the
+/// same batch. `current_transaction` is the position of the transaction the program failed
+/// on (see `UtxoAdvice::current_transaction`).
+/// There is no equivalent error if the advice data is inconsistent (e.g. it does not match
+/// the hashes given to the program), in which case the advice provider is at fault rather
+/// than the batch.
+pub fn batch_error(
+    error: &ExecutionError,
+    current_transaction: Option<usize>,
+) -> Option<BatchError> {
+    let error = match error {
+        ExecutionError::FailedAssertion(_, err_code::EMPTY_BATCH) => {
+            return Some(BatchError::Empty)
+        }
+        ExecutionError::FailedAssertion(_, code) => state_error(*code)?,
+        // There is no signature for an input owner (or issuer) in the signed transaction
+        ExecutionError::FailedSignatureGeneration(_) => TransactionError::MissingSignature.into(),
+        _ => return None,
+    };
+    Some(BatchError::InvalidTransaction {
+        index: current_transaction?,
+        error,
+    })
+}
+
+/// Error codes of the `assert` instructions of the program (the `const` declarations of
+/// `utxo.masm`).
+pub mod err_code {
+    pub const UNKNOWN_UTXO_HASH: u32 = 100;
+    pub const INVALID_TRANSACTION_SIZE: u32 = 101;
+    pub const NO_INPUTS: u32 = 102;
+    pub const LEAF_OCCUPIED: u32 = 103;
+    pub const EXCESSIVE_OUTPUT: u32 = 104;
+    pub const UNSPENT_INPUT: u32 = 105;
+    pub const EMPTY_BATCH: u32 = 106;
+    pub const ALREADY_SPENT: u32 = 107;
+    pub const LOCKED_INPUT: u32 = 108;
+    pub const INVALID_MULTISIG: u32 = 109;
+    pub const MISSING_SIGNATURE: u32 = 110;
+    pub const INVALID_SIGNER: u32 = 111;
+    pub const UNKNOWN_ASSET: u32 = 112;
+    pub const EMPTY_MINT: u32 = 113;
+    pub const INVALID_MINTED_SUPPLY: u32 = 114;
+    pub const MINT_CAP_EXCEEDED: u32 = 115;
+    pub const VALUE_OVERFLOW: u32 = 116;
+    pub const INVALID_TRANSACTION_WORDS: u32 = 117;
+    pub const INVALID_TRANSACTION_DATA: u32 = 118;
+    pub const INVALID_UTXO_DATA: u32 = 119;
+    pub const INVALID_MINT_POLICY: u32 = 120;
+    pub const INVALID_BATCH_DATA: u32 = 121;
+    pub const INVALID_HASH_RANGE: u32 = 122;
+    pub const UNKNOWN_SIGNER: u32 = 123;
+    pub const NULLIFIED_LEAF: u32 = 124;
+    pub const HEIGHT_OVERFLOW: u32 = 125;
+    pub const SUPPLY_OVERFLOW: u32 = 126;
+    pub const DUPLICATE_KEY: u32 = 127;
+    pub const INVALID_HEIGHT: u32 = 128;
+}
+
+/// Error of the Rust implementation for an error code of the program.
+fn state_error(code: u32) -> Option<StateError> {
+    use err_code::*;
+    let error = match code {
+        // Only the signature verification of the standard library asserts without a code
+        0 => TransactionError::InvalidSignature.into(),
+        UNKNOWN_UTXO_HASH => StateError::UnknownUtxoHash,
+        NO_INPUTS => TransactionError::NoInputs.into(),
+        LEAF_OCCUPIED | NULLIFIED_LEAF => StateError::LeafOccupied,
+        EXCESSIVE_OUTPUT => TransactionError::ExcessiveOutput.into(),
+        UNSPENT_INPUT => TransactionError::UnspentInput.into(),
+        ALREADY_SPENT => StateError::AlreadySpent,
+        LOCKED_INPUT => TransactionError::LockedInput.into(),
+        INVALID_MULTISIG | DUPLICATE_KEY => TransactionError::InvalidMultisig.into(),
+        MISSING_SIGNATURE => TransactionError::MissingSignature.into(),
+        INVALID_SIGNER | UNKNOWN_SIGNER => TransactionError::InvalidSignature.into(),
+        // The advice provider gives an empty policy for assets without one
+        UNKNOWN_ASSET | INVALID_MINT_POLICY => StateError::UnknownAsset,
+        EMPTY_MINT => TransactionError::EmptyMint.into(),
+        INVALID_MINTED_SUPPLY => StateError::InvalidMintedSupply,
+        MINT_CAP_EXCEEDED | SUPPLY_OVERFLOW => StateError::MintCapExceeded,
+        VALUE_OVERFLOW => TransactionError::ValueOverflow.into(),
+        HEIGHT_OVERFLOW => TransactionError::HeightOverflow.into(),
+        INVALID_HEIGHT => StateError::HeightOverflow,
+        _ => return None,
+    };
+    Some(error)
+}
+
+/// Compile the MASM program (`code`) for a state tree of the given depth.
+pub fn compile_program(code: &str, tree_depth: u8) -> anyhow::Result<Program> {
+    let code = set_tree_depth(code, tree_depth);
+    let assembler = Assembler::default().with_library(&StdLibrary::default())?;
+    let program = assembler.compile(code)?;
+    Ok(program)
+}
+
+/// Replace the values of the constants in the MASM code which depend on the depth of
+/// the state tree, so that the program matches the `State` implementation.
+pub fn set_tree_depth(code: &str, tree_depth: u8) -> String {
+    let mask = leaf_index_mask(tree_depth);
+    let constants = [
+        ("MERKLE_TREE_DEPTH", u64::from(tree_depth)),
+        ("LEAF_INDEX_LOW_MASK", mask & u64::from(u32::MAX)),
+        ("LEAF_INDEX_HIGH_MASK", mask >> 32),
+    ];
+    code.lines().fold(String::new(), |mut acc, line| {
+        let constant = constants
+            .iter()
+            .find(|(name, _)| line.starts_with(&format!("const.{name}=")));
+        match constant {
+            Some((name, value)) => writeln!(&mut acc, "const.{name}={value}"),
+            None => writeln!(&mut acc, "{line}"),
+        }
+        .unwrap();
+        acc
+    })
+}
+
+impl ProveOutput {
+    pub fn write_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let serialized = SerializedProveOutput::from(self);
+        let data = serde_json::to_string_pretty(&serialized)?;
+        std::fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    pub fn read_from_file(path: &Path) -> anyhow::Result<Self> {
+        let serialized: SerializedProveOutput =
+            utils::read_json_file(path).context("Failed to read proof file")?;
+        serialized.try_into()
+    }
+
+    /// The new state root is the top word of the output stack (in reverse order).
+    pub fn state_root(&self) -> Word {
+        output_word(&self.stack_outputs, 0)
+    }
+
+    /// The new nullifier root is the second word of the output stack (in reverse order).
+    pub fn nullifier_root(&self) -> Word {
+        output_word(&self.stack_outputs, 1)
+    }
+}
+
+impl RunOutput {
+    /// See `ProveOutput::state_root`.
+    pub fn state_root(&self) -> Word {
+        output_word(&self.stack_outputs, 0)
+    }
+
+    /// See `ProveOutput::nullifier_root`.
+    pub fn nullifier_root(&self) -> Word {
+        output_word(&self.stack_outputs, 1)
+    }
+}
+
+fn output_word(stack_outputs: &StackOutputs, index: usize) -> Word {
+    let mut word = Word::default();
+    let stack = stack_outputs.stack().iter().skip(4 * index);
+    for (e, x) in word.iter_mut().rev().zip(stack) {
+        *e = Felt::new(*x);
+    }
+    word
+}
+
+impl TryFrom<SerializedProveOutput> for ProveOutput {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SerializedProveOutput) -> Result<Self, Self::Error> {
+        let program_hash: [u8; 32] = value
+            .program_hash
+            .bytes
+            .try_into()
+            .map_err(|_| anyhow::Error::msg("Program hash must be 32 bytes"))?;
+        let stack: Vec<u64> = value
+            .stack_outputs
+            .iter()
+            .map(|x| x.parse())
+            .collect::<Result<_, _>>()
+            .context("Failed to parse stack outputs")?;
+        let overflow_addrs: Vec<u64> = value
+            .overflow_addrs
+            .iter()
+            .map(|x| x.parse())
+            .collect::<Result<_, _>>()
+            .context("Failed to parse overflow addresses")?;
+        let stack_outputs = StackOutputs::new(stack, overflow_addrs)
+            .map_err(|e| anyhow::Error::msg(format!("Invalid stack outputs: {e:?}")))?;
+        let proof_bytes = hex::decode(value.proof).context("Failed to decode proof")?;
+        let proof = ExecutionProof::from_bytes(&proof_bytes)
+            .map_err(|e| anyhow::Error::msg(format!("Failed to deserialize proof: {e:?}")))?;
+        Ok(Self {
+            program_hash,
+            stack_outputs,
+            proof,
+        })
+    }
+}
+
+impl From<&ProveOutput> for SerializedProveOutput {
+    fn from(value: &ProveOutput) -> Self {
+        let serialized_outputs: Vec<String> = value
+            .stack_outputs
+            .stack()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let serialized_overflows: Vec<String> = value
+            .stack_outputs
+            .overflow_addrs()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let serialized_proof = hex::encode(value.proof.to_bytes());
+        Self {
+            program_hash: HexString {
+                bytes: value.program_hash.to_vec(),
+            },
+            stack_outputs: serialized_outputs,
+            overflow_addrs: serialized_overflows,
+            proof: serialized_proof,
+        }
+    }
+}
+
+// The operand stack starts as the state height, batch hash, state root, nullifier root and
+// finally the owner of the fee UTXOs (the number of transactions is part of the batch)
+pub fn prepare_stack_inputs(state: &State, batch: &Batch, fee_collector: Word) -> StackInputs {
+    let height = Felt::new(state.height);
+    let batch_hash = batch.hash();
+    let state_root = state.get_root();
+    let nullifier_root = state.get_nullifier_root();
+
+    // Insert stack elements in reverse, stack top is at the rear
+    let input_stack: Vec<Felt> = fee_collector
+        .into_iter()
+        .chain(nullifier_root)
+        .chain(state_root)
+        .chain(batch_hash)
+        .chain(std::iter::once(height))
+        .collect();
+
+    StackInputs::new(input_stack)
+}
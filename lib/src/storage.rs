@@ -0,0 +1,262 @@
+//! Persistent history of the state.
+//!
+//! The store is an append-only log on disk (one JSON record per line). The first record
+//! holds the genesis state and each following record holds a batch of transactions which
+//! was applied to the state. Every record also holds the state root after it, so the state
+//! can be rebuilt by replaying the log up to any historical root.
+
+use crate::{
+    utils::HexString,
+    utxo::{Batch, SerializedSignedTransaction, SignedTransaction, State},
+};
+use anyhow::Context;
+use miden_crypto::Word;
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogEntry {
+    Genesis {
+        state: State,
+    },
+    Batch {
+        transactions: Vec<SerializedSignedTransaction>,
+        fee_collector: HexString,
+        /// Height of the state when the batch was applied.
+        #[serde(default)]
+        height: u64,
+    },
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct LogRecord {
+    pub entry: LogEntry,
+    /// State root after applying the entry.
+    pub root: HexString,
+}
+
+/// A batch recorded in the log, with what is needed to apply it again (or prove it).
+#[derive(Debug, Clone)]
+pub struct BatchRecord {
+    pub batch: Batch,
+    pub fee_collector: Word,
+    /// Height of the state when the batch was applied.
+    pub height: u64,
+    /// State root the batch was applied to.
+    pub previous_root: Word,
+}
+
+pub struct Store {
+    path: PathBuf,
+}
+
+impl Store {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Start a new history from the given state.
+    /// Fails if there is already a history at the store path.
+    pub fn create(path: &Path, genesis: &State) -> anyhow::Result<Self> {
+        if path.exists() {
+            anyhow::bail!("State store {path:?} already exists; remove it to start a new history");
+        }
+        let store = Self::new(path);
+        store.append(LogRecord {
+            entry: LogEntry::Genesis {
+                state: genesis.clone(),
+            },
+            root: genesis.get_root().into(),
+        })?;
+        Ok(store)
+    }
+
+    /// Apply the batch to `state` (which must be the latest state of the store, set to the
+    /// height to apply the batch at) and record it in the log.
+    pub fn apply(
+        &self,
+        state: &mut State,
+        batch: Batch,
+        fee_collector: Word,
+    ) -> anyhow::Result<()> {
+        let transactions = batch.transactions.clone();
+        state.process_batch(batch, fee_collector)?;
+        self.append(LogRecord {
+            entry: LogEntry::Batch {
+                transactions: transactions.into_iter().map(Into::into).collect(),
+                fee_collector: fee_collector.into(),
+                height: state.height,
+            },
+            root: state.get_root().into(),
+        })
+    }
+
+    /// Rebuild the latest state by replaying the whole log.
+    pub fn load(&self) -> anyhow::Result<State> {
+        self.replay(None)
+    }
+
+    /// Rebuild the state as it was when its root was `root`.
+    pub fn load_at(&self, root: Word) -> anyhow::Result<State> {
+        self.replay(Some(root))
+    }
+
+    /// All the roots the state has had, from the genesis to the latest one.
+    pub fn roots(&self) -> anyhow::Result<Vec<Word>> {
+        self.records()?
+            .into_iter()
+            .map(|record| record.root.try_into())
+            .collect()
+    }
+
+    /// All the batches of the log, in the order they were applied.
+    pub fn batches(&self) -> anyhow::Result<Vec<BatchRecord>> {
+        let mut previous_root = None;
+        let mut batches = Vec::new();
+        for (i, record) in self.records()?.into_iter().enumerate() {
+            if let LogEntry::Batch {
+                transactions,
+                fee_collector,
+                height,
+            } = record.entry
+            {
+                let transactions = transactions
+                    .into_iter()
+                    .map(SignedTransaction::try_from)
+                    .collect::<anyhow::Result<_>>()?;
+                batches.push(BatchRecord {
+                    batch: Batch { transactions },
+                    fee_collector: fee_collector.try_into()?,
+                    height,
+                    previous_root: previous_root.with_context(|| {
+                        format!("Record {i} of the state store is out of place")
+                    })?,
+                });
+            }
+            previous_root = Some(record.root.try_into()?);
+        }
+        Ok(batches)
+    }
+
+    fn replay(&self, target_root: Option<Word>) -> anyhow::Result<State> {
+        let mut state: Option<State> = None;
+        for (i, record) in self.records()?.into_iter().enumerate() {
+            let current = match (record.entry, state) {
+                (LogEntry::Genesis { state }, None) => state,
+                (
+                    LogEntry::Batch {
+                        transactions,
+                        fee_collector,
+                        height,
+                    },
+                    Some(mut state),
+                ) => {
+                    state
+                        .set_height(height)
+                        .with_context(|| format!("Failed to replay record {i}"))?;
+                    let transactions = transactions
+                        .into_iter()
+                        .map(SignedTransaction::try_from)
+                        .collect::<anyhow::Result<_>>()?;
+                    state
+                        .process_batch(Batch { transactions }, fee_collector.try_into()?)
+                        .with_context(|| format!("Failed to replay record {i}"))?;
+                    state
+                }
+                _ => anyhow::bail!("Record {i} of the state store is out of place"),
+            };
+            let root: Word = record.root.try_into()?;
+            if current.get_root() != root {
+                anyhow::bail!("State root after record {i} does not match the store");
+            }
+            if target_root == Some(root) {
+                return Ok(current);
+            }
+            state = Some(current);
+        }
+
+        match (state, target_root) {
+            (Some(state), None) => Ok(state),
+            (None, _) => anyhow::bail!("State store {:?} is empty", self.path),
+            (Some(_), Some(_)) => anyhow::bail!("State root not found in the store"),
+        }
+    }
+
+    fn records(&self) -> anyhow::Result<Vec<LogRecord>> {
+        let data = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read state store {:?}", self.path))?;
+        data.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).context("Failed to parse state store record"))
+            .collect()
+    }
+
+    fn append(&self, record: LogRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open state store {:?}", self.path))?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_replay() {
+    use crate::utxo::{Key, Output, Transaction, Utxo, NATIVE_ASSET};
+    use miden_crypto::Felt;
+
+    let path = std::env::temp_dir().join(format!("utxo-miden-store-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let key = Key::random().unwrap();
+    let initial_utxo = Utxo {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state.insert(initial_utxo.clone()).unwrap();
+    let genesis_root = state.get_root();
+    let store = Store::create(&path, &state).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![initial_utxo.hash()],
+        outputs: vec![Output {
+            owner: key.owner,
+            asset_id: NATIVE_ASSET,
+            value: Felt::new(90),
+            unlock_height: None,
+        }],
+        fee: Felt::new(10),
+        mint: None,
+    };
+    let signed_tx = SignedTransaction::new(transaction, &[key.pair]).unwrap();
+    state.set_height(5).unwrap();
+    store
+        .apply(&mut state, signed_tx.into(), Word::default())
+        .unwrap();
+
+    assert_eq!(store.load().unwrap().get_root(), state.get_root());
+    assert_eq!(store.load().unwrap().height, 5);
+    assert_eq!(
+        store.load_at(genesis_root).unwrap().get_root(),
+        genesis_root
+    );
+    assert_eq!(store.roots().unwrap(), vec![genesis_root, state.get_root()]);
+    let batches = store.batches().unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].previous_root, genesis_root);
+    assert_eq!(batches[0].height, 5);
+
+    std::fs::remove_file(&path).unwrap();
+}
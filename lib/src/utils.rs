@@ -1,25 +1,46 @@
-use crate::{config::Config, utxo::State};
+use crate::utxo::{Batch, SerializedSignedTransaction, SignedTransaction};
 use anyhow::Context;
 use miden_core::{Felt, Word};
 use std::{path::Path, str::FromStr};
 use winter_utils::{Deserializable, Serializable};
 
-pub fn write_state(state: &State, config: &Config) -> anyhow::Result<()> {
-    let state_root: String = HexString::from(state.get_root()).into();
-    println!("State root = {state_root}");
-    let output = serde_json::to_string_pretty(&state)?;
-    let output_path = config.no_zk_path.join("state.json");
-    std::fs::write(&output_path, output).context("Failed to write state file")?;
-    println!("State written to {output_path:?}");
-    Ok(())
-}
-
 pub fn read_json_file<T: for<'a> serde::Deserialize<'a>>(path: &Path) -> anyhow::Result<T> {
     let data = std::fs::read_to_string(path)?;
     let t: T = serde_json::from_str(&data)?;
     Ok(t)
 }
 
+/// Read a batch of signed transactions (see `SerializedSignedTransaction`).
+/// The path is either a JSON file containing an array of transactions or a directory
+/// of JSON files with one transaction each (applied in the order of their file names).
+pub fn read_batch(path: &Path) -> anyhow::Result<Batch> {
+    let serialized: Vec<SerializedSignedTransaction> = if path.is_dir() {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry_path = entry?.path();
+            if entry_path
+                .extension()
+                .map(|ext| ext == "json")
+                .unwrap_or(false)
+            {
+                paths.push(entry_path);
+            }
+        }
+        paths.sort();
+        paths
+            .iter()
+            .map(|p| read_json_file(p).with_context(|| format!("Failed to read {p:?}")))
+            .collect::<anyhow::Result<_>>()?
+    } else {
+        read_json_file(path).context("Failed to read batch file")?
+    };
+    let transactions = serialized
+        .into_iter()
+        .map(SignedTransaction::try_from)
+        .collect::<anyhow::Result<_>>()?;
+    Ok(Batch { transactions })
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 #[serde(try_from = "String", into = "String")]
 pub struct HexString {
@@ -80,6 +101,14 @@ impl From<Word> for HexString {
     }
 }
 
+impl From<Felt> for HexString {
+    fn from(value: Felt) -> Self {
+        let mut bytes = Vec::new();
+        value.write_into(&mut bytes);
+        Self { bytes }
+    }
+}
+
 fn felt_from_bytes(bytes: &[u8]) -> anyhow::Result<Felt> {
     Felt::read_from_bytes(bytes)
         .map_err(|e| anyhow::Error::msg(format!("Failed to parse field element {e:?}")))
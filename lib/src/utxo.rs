@@ -0,0 +1,1856 @@
+use crate::utils::HexString;
+use miden_crypto::{
+    dsa::rpo_falcon512::{FalconError, KeyPair, Signature},
+    hash::rpo::{Rpo256, RpoDigest},
+    merkle::SimpleSmt,
+    utils::{Deserializable, Serializable},
+    Felt, StarkField, Word, WORD_SIZE, ZERO,
+};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedKey", into = "SerializedKey")]
+pub struct Key {
+    pub pair: KeyPair,
+    pub owner: Word,
+}
+
+impl Key {
+    pub fn random() -> anyhow::Result<Self> {
+        let pair = KeyPair::new()?;
+        let owner = pair.public_key().into();
+        Ok(Self { pair, owner })
+    }
+}
+
+/// Id of the asset transaction fees are paid in.
+pub const NATIVE_ASSET: Word = [ZERO; WORD_SIZE];
+
+/// Largest value a UTXO, an output, a fee or a mint can have. Values are limited to 32 bits
+/// so that the totals the MASM program sums as field elements can never wrap around the
+/// field modulus (that would take 2^32 values).
+pub const MAX_VALUE: u64 = u32::MAX as u64;
+
+/// Largest height the state or an unlock height can have. Heights are limited to 32 bits so
+/// that the MASM program, which compares them as field elements, gives the same result as
+/// comparing them as integers.
+pub const MAX_HEIGHT: u64 = u32::MAX as u64;
+
+/// Largest total value which can be minted for an asset (and largest cap of a minting policy).
+/// It is far enough below the field modulus that the MASM program can add a mint to the
+/// minted total as field elements without wrapping around.
+pub const MAX_SUPPLY: u64 = 1 << 62;
+
+/// Unspent transaction output held in the state.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedUtxo", into = "SerializedUtxo")]
+pub struct Utxo {
+    pub owner: Word,
+    /// Asset the value is in (`NATIVE_ASSET` for the asset fees are paid in).
+    pub asset_id: Word,
+    pub value: Felt,
+    /// Makes the UTXO unique (and so its hash), even if another UTXO has the same owner
+    /// and value. For UTXOs created by a transaction it is given by `output_serial`.
+    pub serial: Word,
+    /// The UTXO cannot be spent while the state height is lower than this.
+    /// It is at most `MAX_HEIGHT`.
+    pub unlock_height: Option<u64>,
+}
+impl Utxo {
+    /// Serialize the UTXO as field elements: the owner, the serial, the asset id, the value
+    /// and then the unlock height (0 if there is none).
+    pub fn serialize(&self) -> Vec<Felt> {
+        let mut output = Vec::with_capacity(3 * WORD_SIZE + 2);
+        output.extend_from_slice(&self.owner);
+        output.extend_from_slice(&self.serial);
+        output.extend_from_slice(&self.asset_id);
+        output.push(self.value);
+        output.push(Felt::new(self.unlock_height.unwrap_or(0)));
+        output
+    }
+
+    /// Whether the UTXO cannot be spent yet at the given state height.
+    pub fn is_locked(&self, height: u64) -> bool {
+        self.unlock_height
+            .is_some_and(|unlock_height| unlock_height > height)
+    }
+
+    pub fn hash(&self) -> Word {
+        let elems = self.serialize();
+        let h = Rpo256::hash_elements(&elems);
+        h.into()
+    }
+}
+
+/// Serial of the UTXO created by output `index` of the transaction with hash `tx_hash`
+/// (the fee UTXO comes after all the outputs of the transaction).
+/// Transaction hashes are unique because each transaction spends UTXOs which are unique,
+/// so the serials are unique too.
+pub fn output_serial(tx_hash: Word, index: usize) -> Word {
+    let index = [Felt::new(index as u64), ZERO, ZERO, ZERO];
+    Rpo256::merge(&[RpoDigest::from(tx_hash), RpoDigest::from(index)]).into()
+}
+
+/// UTXO to create in a transaction. The serial of the UTXO is only given when the
+/// transaction is applied (see `Transaction::output_utxos`).
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedOutput", into = "SerializedOutput")]
+pub struct Output {
+    pub owner: Word,
+    pub asset_id: Word,
+    pub value: Felt,
+    /// Height from which the created UTXO can be spent (see `Utxo::unlock_height`).
+    pub unlock_height: Option<u64>,
+}
+
+impl Output {
+    fn serialize_inner(&self, target: &mut Vec<Felt>) {
+        target.extend_from_slice(&self.owner);
+        target.extend_from_slice(&[
+            self.value,
+            Felt::new(self.unlock_height.unwrap_or(0)),
+            ZERO,
+            ZERO,
+        ]);
+        target.extend_from_slice(&self.asset_id);
+    }
+}
+
+/// New value of an asset created by a transaction, which must be signed by the issuer of the
+/// asset (see `MintPolicy`).
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedMint", into = "SerializedMint")]
+pub struct Mint {
+    pub asset_id: Word,
+    pub value: Felt,
+    /// Total value minted for the asset before this mint, which must match its minting policy.
+    /// This stops the same mint from being applied twice.
+    pub minted: u64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedTransaction", into = "SerializedTransaction")]
+pub struct Transaction {
+    /// Hashes of input UTXOs.
+    pub inputs: Vec<Word>,
+    /// List of newly created UTXOs.
+    /// It must be true that `outputs.map(|x| x.value).sum() + fee == inputs.map(|x| x.value).sum()`
+    /// for each asset, with every value at most `MAX_VALUE`.
+    pub outputs: Vec<Output>,
+    /// Value paid to the fee collector chosen by whoever applies the transaction to the state.
+    pub fee: Felt,
+    /// Value minted by the transaction, which is added to its inputs.
+    /// A transaction which mints does not need to have any inputs.
+    pub mint: Option<Mint>,
+}
+
+impl Transaction {
+    /// Serialize the transaction as field elements: the fee (padded to a word), input hashes,
+    /// the outputs and then the mint (if any). Each output takes three words (the owner, the
+    /// value and unlock height padded with zeros, and the asset id) and the mint takes two
+    /// (the asset id and `[value, minted, 0, 0]`) so that they are word-aligned when the MASM
+    /// program loads the transaction into memory.
+    pub fn to_elems(&self) -> Vec<Felt> {
+        let mut elems =
+            Vec::with_capacity(WORD_SIZE * (1 + self.inputs.len() + 3 * self.outputs.len() + 2));
+        elems.extend_from_slice(&[self.fee, ZERO, ZERO, ZERO]);
+        for input in self.inputs.iter() {
+            elems.extend_from_slice(input);
+        }
+        for u in self.outputs.iter() {
+            u.serialize_inner(&mut elems);
+        }
+        if let Some(mint) = &self.mint {
+            elems.extend_from_slice(&mint.asset_id);
+            elems.extend_from_slice(&[mint.value, Felt::new(mint.minted), ZERO, ZERO]);
+        }
+        elems
+    }
+
+    pub fn hash(&self) -> Word {
+        let elems = self.to_elems();
+        let h = Rpo256::hash_elements(&elems);
+        h.into()
+    }
+
+    /// Check the transaction against the UTXOs it spends (given in the same order as `self.inputs`).
+    /// The minted value counts as an input of its asset.
+    pub fn verify(&self, inputs: &[Utxo]) -> Result<(), TransactionError> {
+        if self.inputs.is_empty() && self.mint.is_none() {
+            return Err(TransactionError::NoInputs);
+        }
+        if self.mint.as_ref().is_some_and(|mint| mint.value == ZERO) {
+            return Err(TransactionError::EmptyMint);
+        }
+        if inputs.len() != self.inputs.len()
+            || inputs
+                .iter()
+                .zip(self.inputs.iter())
+                .any(|(utxo, hash)| &utxo.hash() != hash)
+        {
+            return Err(TransactionError::InvalidInputHash);
+        }
+        // Input and output totals of each asset, in the order the assets are first seen
+        // (the fee is paid in the native asset)
+        let mut totals = Vec::new();
+        for u in inputs {
+            add_value(&mut asset_totals(&mut totals, u.asset_id).1, u.value)?;
+        }
+        if let Some(mint) = &self.mint {
+            add_value(&mut asset_totals(&mut totals, mint.asset_id).1, mint.value)?;
+        }
+        for u in self.outputs.iter() {
+            if u.unlock_height.is_some_and(|height| height > MAX_HEIGHT) {
+                return Err(TransactionError::HeightOverflow);
+            }
+            add_value(&mut asset_totals(&mut totals, u.asset_id).2, u.value)?;
+        }
+        if self.fee != ZERO {
+            add_value(&mut asset_totals(&mut totals, NATIVE_ASSET).2, self.fee)?;
+        }
+        for (_, total_input, total_output) in totals {
+            if total_output > total_input {
+                return Err(TransactionError::ExcessiveOutput);
+            }
+            if total_output < total_input {
+                return Err(TransactionError::UnspentInput);
+            }
+        }
+        Ok(())
+    }
+
+    /// The UTXOs created by the transaction: one per output followed by the UTXO crediting
+    /// the fee to `fee_collector` (there is none if the fee is 0).
+    pub fn output_utxos(&self, fee_collector: Word) -> Vec<Utxo> {
+        let hash = self.hash();
+        let fee_output = (self.fee != ZERO).then(|| Output {
+            owner: fee_collector,
+            asset_id: NATIVE_ASSET,
+            value: self.fee,
+            unlock_height: None,
+        });
+        self.outputs
+            .iter()
+            .chain(fee_output.as_ref())
+            .enumerate()
+            .map(|(index, output)| Utxo {
+                owner: output.owner,
+                asset_id: output.asset_id,
+                value: output.value,
+                serial: output_serial(hash, index),
+                unlock_height: output.unlock_height,
+            })
+            .collect()
+    }
+}
+
+/// Owner of UTXOs which can only be spent with the signatures of `threshold` of its `keys`.
+/// The owner word of such UTXOs is the commitment to the key set given by `Multisig::owner`.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedMultisig", into = "SerializedMultisig")]
+pub struct Multisig {
+    pub threshold: usize,
+    /// Falcon public keys of the owners.
+    pub keys: Vec<Word>,
+}
+
+impl Multisig {
+    /// Key set of the given keys, sorted in increasing order of their first element.
+    pub fn new(threshold: usize, mut keys: Vec<Word>) -> anyhow::Result<Self> {
+        if threshold == 0 || threshold > keys.len() {
+            anyhow::bail!(
+                "Threshold must be between 1 and the number of keys ({})",
+                keys.len()
+            );
+        }
+        keys.sort_by_key(|key| key[0].as_int());
+        let multisig = Self { threshold, keys };
+        if !multisig.has_distinct_keys() {
+            anyhow::bail!("The same key is given more than once (or two keys start alike)");
+        }
+        Ok(multisig)
+    }
+
+    /// Whether the keys are in strictly increasing order of their first element, so that no
+    /// key is given twice (it would count twice towards the threshold). This is cheap to
+    /// check in the MASM program too.
+    pub fn has_distinct_keys(&self) -> bool {
+        self.keys
+            .windows(2)
+            .all(|pair| pair[0][0].as_int() < pair[1][0].as_int())
+    }
+
+    /// Serialize the key set as field elements: `[threshold, number_of_keys, 0, 0]`
+    /// followed by the keys.
+    pub fn to_elems(&self) -> Vec<Felt> {
+        let mut elems = Vec::with_capacity(WORD_SIZE * (1 + self.keys.len()));
+        elems.extend_from_slice(&[
+            Felt::new(self.threshold as u64),
+            Felt::new(self.keys.len() as u64),
+            ZERO,
+            ZERO,
+        ]);
+        for key in self.keys.iter() {
+            elems.extend_from_slice(key);
+        }
+        elems
+    }
+
+    /// The owner of UTXOs spendable by this key set.
+    pub fn owner(&self) -> Word {
+        let elems = self.to_elems();
+        let h = Rpo256::hash_elements(&elems);
+        h.into()
+    }
+}
+
+/// Policy for minting new value of an asset. Policies are stored in the state tree like UTXOs,
+/// at the leaf given by their `key`, so the state root commits to them.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedMintPolicy", into = "SerializedMintPolicy")]
+pub struct MintPolicy {
+    pub asset_id: Word,
+    /// Owner whose signature authorizes mints (a single key or a multisig owner).
+    pub issuer: Word,
+    /// Maximum total value which can be minted, there is no limit (but `MAX_SUPPLY`) if not
+    /// given.
+    pub cap: Option<u64>,
+    /// Total value minted so far, at most `MAX_SUPPLY`.
+    pub minted: u64,
+}
+
+impl MintPolicy {
+    pub fn new(asset_id: Word, issuer: Word, cap: Option<u64>) -> Self {
+        Self {
+            asset_id,
+            issuer,
+            cap,
+            minted: 0,
+        }
+    }
+
+    /// The key giving the leaf of the state tree the policy of the asset is stored at.
+    pub fn key(asset_id: Word) -> Word {
+        Rpo256::merge(&[RpoDigest::from(asset_id), RpoDigest::default()]).into()
+    }
+
+    /// Serialize the policy as field elements: the issuer, the asset id and then
+    /// `[minted, cap, has_cap, 0]` (the cap is 0 if there is none).
+    pub fn to_elems(&self) -> Vec<Felt> {
+        let mut elems = Vec::with_capacity(3 * WORD_SIZE);
+        elems.extend_from_slice(&self.issuer);
+        elems.extend_from_slice(&self.asset_id);
+        elems.extend_from_slice(&[
+            Felt::new(self.minted),
+            Felt::new(self.cap.unwrap_or(0)),
+            Felt::new(u64::from(self.cap.is_some())),
+            ZERO,
+        ]);
+        elems
+    }
+
+    pub fn hash(&self) -> Word {
+        let elems = self.to_elems();
+        let h = Rpo256::hash_elements(&elems);
+        h.into()
+    }
+}
+
+/// Signatures authorizing the spending of one input of a transaction.
+#[derive(Debug, Clone)]
+pub enum InputSignature {
+    /// Signature of the owner of the input, which is a single public key.
+    Single(Signature),
+    /// Signatures of keys of the multisig owner of the input, as `(key index, signature)`
+    /// in increasing order of key index.
+    Multisig {
+        owner: Multisig,
+        signatures: Vec<(usize, Signature)>,
+    },
+}
+
+/// Keys signing for one input of a transaction.
+pub enum Signer<'a> {
+    Key(&'a KeyPair),
+    /// Keys of a multisig owner (at least `threshold` of them).
+    Multisig {
+        owner: &'a Multisig,
+        keys: &'a [KeyPair],
+    },
+}
+
+/// The `(asset_id, input_total, output_total)` entry of the asset, added if it is not there yet.
+fn asset_totals(totals: &mut Vec<(Word, u64, u64)>, asset_id: Word) -> &mut (Word, u64, u64) {
+    let index = match totals.iter().position(|(id, _, _)| id == &asset_id) {
+        Some(index) => index,
+        None => {
+            totals.push((asset_id, 0, 0));
+            totals.len() - 1
+        }
+    };
+    &mut totals[index]
+}
+
+/// Add the value to the total, checking it is not above `MAX_VALUE`.
+fn add_value(total: &mut u64, value: Felt) -> Result<(), TransactionError> {
+    let value = value.as_int();
+    if value > MAX_VALUE {
+        return Err(TransactionError::ValueOverflow);
+    }
+    *total = total
+        .checked_add(value)
+        .ok_or(TransactionError::ValueOverflow)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct SignedTransaction {
+    pub transaction: Transaction,
+    /// One signature (or set of multisig signatures) per input, in the same order as
+    /// `transaction.inputs`, followed by the signature of the issuer if the transaction mints.
+    pub signatures: Vec<InputSignature>,
+}
+
+impl SignedTransaction {
+    /// Sign the transaction with one key per input (in the same order as `transaction.inputs`),
+    /// followed by the key of the issuer if the transaction mints.
+    pub fn new(transaction: Transaction, keys: &[KeyPair]) -> Result<Self, FalconError> {
+        let message = transaction.hash();
+        let signatures = keys
+            .iter()
+            .map(|key| key.sign(message).map(InputSignature::Single))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            transaction,
+            signatures,
+        })
+    }
+
+    /// Sign the transaction with one signer per input (in the same order as `transaction.inputs`),
+    /// followed by the issuer if the transaction mints.
+    pub fn with_signers(transaction: Transaction, signers: &[Signer]) -> anyhow::Result<Self> {
+        let message = transaction.hash();
+        let mut signatures = Vec::with_capacity(signers.len());
+        for signer in signers {
+            let signature = match signer {
+                Signer::Key(key) => InputSignature::Single(key.sign(message)?),
+                Signer::Multisig { owner, keys } => {
+                    let mut key_signatures = Vec::with_capacity(keys.len());
+                    for key in keys.iter() {
+                        let public_key: Word = key.public_key().into();
+                        let index = owner
+                            .keys
+                            .iter()
+                            .position(|k| k == &public_key)
+                            .ok_or_else(|| anyhow::anyhow!("Key is not part of the multisig"))?;
+                        key_signatures.push((index, key.sign(message)?));
+                    }
+                    key_signatures.sort_by_key(|(index, _)| *index);
+                    key_signatures.dedup_by_key(|(index, _)| *index);
+                    InputSignature::Multisig {
+                        owner: (*owner).clone(),
+                        signatures: key_signatures,
+                    }
+                }
+            };
+            signatures.push(signature);
+        }
+        Ok(Self {
+            transaction,
+            signatures,
+        })
+    }
+
+    /// Check the transaction and its signatures against the UTXOs it spends, at the given
+    /// state height (inputs which are still locked cannot be spent). `issuer` is the issuer
+    /// of the asset minted by the transaction (see `State::check_mint`), if it mints.
+    /// As in `State::process_tx`, each input is checked in turn (see `verify_input`), then the
+    /// signature of the issuer and only then the values (see `Transaction::verify`).
+    pub fn verify(
+        &self,
+        inputs: &[Utxo],
+        issuer: Option<Word>,
+        height: u64,
+    ) -> Result<(), TransactionError> {
+        let tx = &self.transaction;
+        if tx.inputs.is_empty() && tx.mint.is_none() {
+            return Err(TransactionError::NoInputs);
+        }
+        if inputs.len() != tx.inputs.len() {
+            return Err(TransactionError::InvalidInputHash);
+        }
+        if tx.mint.is_some() != issuer.is_some() {
+            return Err(TransactionError::MissingSignature);
+        }
+        self.check_signature_count()?;
+        for (position, input) in inputs.iter().enumerate() {
+            self.verify_input(position, input, height)?;
+        }
+        if let Some(issuer) = issuer {
+            self.verify_issuer(issuer)?;
+        }
+        tx.verify(inputs)
+    }
+
+    /// Check there is one signature per input, followed by one for the issuer if the
+    /// transaction mints.
+    pub fn check_signature_count(&self) -> Result<(), TransactionError> {
+        let tx = &self.transaction;
+        if self.signatures.len() != tx.inputs.len() + usize::from(tx.mint.is_some()) {
+            return Err(TransactionError::MissingSignature);
+        }
+        Ok(())
+    }
+
+    /// Check the input at `position` in the transaction can be spent at the given height, and
+    /// is signed for by its owner.
+    pub fn verify_input(
+        &self,
+        position: usize,
+        input: &Utxo,
+        height: u64,
+    ) -> Result<(), TransactionError> {
+        if input.is_locked(height) {
+            return Err(TransactionError::LockedInput);
+        }
+        let signature = self
+            .signatures
+            .get(position)
+            .ok_or(TransactionError::MissingSignature)?;
+        verify_signature(signature, input.owner, self.transaction.hash())
+    }
+
+    /// Check the mint of the transaction is signed for by the issuer of its asset.
+    pub fn verify_issuer(&self, issuer: Word) -> Result<(), TransactionError> {
+        let signature = self
+            .signatures
+            .get(self.transaction.inputs.len())
+            .ok_or(TransactionError::MissingSignature)?;
+        verify_signature(signature, issuer, self.transaction.hash())
+    }
+}
+
+/// Check the signature (or multisig signatures) of `owner` over the message.
+fn verify_signature(
+    signature: &InputSignature,
+    owner: Word,
+    message: Word,
+) -> Result<(), TransactionError> {
+    match signature {
+        InputSignature::Single(signature) => {
+            if !signature.verify(message, owner) {
+                return Err(TransactionError::InvalidSignature);
+            }
+        }
+        InputSignature::Multisig {
+            owner: multisig,
+            signatures,
+        } => {
+            // The number of signatures is checked before the key set, as in the MASM program
+            if signatures.is_empty() || signatures.len() < multisig.threshold {
+                return Err(TransactionError::MissingSignature);
+            }
+            // The fields of the key set are public, so it may not come from `Multisig::new`
+            if multisig.owner() != owner || !multisig.has_distinct_keys() {
+                return Err(TransactionError::InvalidMultisig);
+            }
+            // Key indices must be increasing so that each key signs at most once
+            let mut next_index = 0;
+            for (index, signature) in signatures {
+                let key = multisig
+                    .keys
+                    .get(*index)
+                    .filter(|_| *index >= next_index)
+                    .ok_or(TransactionError::InvalidSignature)?;
+                if !signature.verify(message, *key) {
+                    return Err(TransactionError::InvalidSignature);
+                }
+                next_index = index + 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ordered list of transactions which are applied to the state (and proven) together.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub transactions: Vec<SignedTransaction>,
+}
+
+impl Batch {
+    /// Serialize the batch as field elements: a word with the number of transactions, then
+    /// for each transaction a word with its number of inputs, the number of elements in
+    /// its serialization and whether it mints, followed by its hash.
+    pub fn to_elems(&self) -> Vec<Felt> {
+        let mut elems = Vec::with_capacity(WORD_SIZE * (1 + 2 * self.transactions.len()));
+        elems.extend_from_slice(&[Felt::new(self.transactions.len() as u64), ZERO, ZERO, ZERO]);
+        for signed_tx in self.transactions.iter() {
+            let tx = &signed_tx.transaction;
+            elems.extend_from_slice(&[
+                Felt::new(tx.inputs.len() as u64),
+                Felt::new(tx.to_elems().len() as u64),
+                Felt::new(u64::from(tx.mint.is_some())),
+                ZERO,
+            ]);
+            elems.extend_from_slice(&tx.hash());
+        }
+        elems
+    }
+
+    pub fn hash(&self) -> Word {
+        let elems = self.to_elems();
+        let h = Rpo256::hash_elements(&elems);
+        h.into()
+    }
+}
+
+impl From<SignedTransaction> for Batch {
+    fn from(value: SignedTransaction) -> Self {
+        Self {
+            transactions: vec![value],
+        }
+    }
+}
+
+/// State of the UTXO system.
+/// The UTXOs are stored in a sparse Merkle tree where each UTXO hash is placed
+/// at the leaf given by `State::leaf_index`. The depth of the tree is chosen when the
+/// state is created; two UTXOs cannot be stored at the same leaf, so smaller trees fill up faster.
+/// The hashes of spent UTXOs (their nullifiers) are kept in a second tree of the same depth,
+/// at the same leaf index, so that a UTXO can never be spent twice.
+/// The minting policies of assets are stored in the UTXO tree too (see `MintPolicy`).
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "SerializedState", into = "SerializedState")]
+pub struct State {
+    pub tree: SimpleSmt,
+    pub nullifiers: SimpleSmt,
+    /// UTXOs in the state, keyed by their leaf index in the tree.
+    pub utxos: BTreeMap<u64, Utxo>,
+    /// Minting policies of the assets which can be minted, keyed by their leaf index in the tree.
+    pub policies: BTreeMap<u64, MintPolicy>,
+    /// Current block height, transactions are applied at this height.
+    pub height: u64,
+}
+
+impl State {
+    pub const DEFAULT_TREE_DEPTH: u8 = SimpleSmt::MAX_DEPTH;
+
+    pub fn empty(tree_depth: u8) -> Result<Self, StateError> {
+        let tree = SimpleSmt::new(tree_depth).map_err(|_| StateError::InvalidTreeDepth)?;
+        let nullifiers = tree.clone();
+        let utxos = BTreeMap::new();
+        let policies = BTreeMap::new();
+        Ok(Self {
+            tree,
+            nullifiers,
+            utxos,
+            policies,
+            height: 0,
+        })
+    }
+
+    /// Move the state to a new block height, which cannot be lower than the current one
+    /// (nor above `MAX_HEIGHT`).
+    pub fn set_height(&mut self, height: u64) -> Result<(), StateError> {
+        if height < self.height {
+            return Err(StateError::HeightDecreased);
+        }
+        if height > MAX_HEIGHT {
+            return Err(StateError::HeightOverflow);
+        }
+        self.height = height;
+        Ok(())
+    }
+
+    pub fn tree_depth(&self) -> u8 {
+        self.tree.depth()
+    }
+
+    /// The leaf of the tree a UTXO is stored at is given by the lowest `tree_depth` bits
+    /// of the first element of its hash.
+    pub fn leaf_index(&self, hash: &Word) -> u64 {
+        hash[0].as_int() & leaf_index_mask(self.tree_depth())
+    }
+
+    pub fn get_utxo(&self, hash: &Word) -> Option<&Utxo> {
+        self.utxos
+            .get(&self.leaf_index(hash))
+            .filter(|u| &u.hash() == hash)
+    }
+
+    pub fn get_policy(&self, asset_id: &Word) -> Option<&MintPolicy> {
+        self.policies
+            .get(&self.leaf_index(&MintPolicy::key(*asset_id)))
+            .filter(|policy| &policy.asset_id == asset_id)
+    }
+
+    /// Check the mint against the minting policy of its asset, which is returned.
+    pub fn check_mint(&self, mint: &Mint) -> Result<&MintPolicy, StateError> {
+        let policy = self
+            .get_policy(&mint.asset_id)
+            .ok_or(StateError::UnknownAsset)?;
+        if mint.value == ZERO {
+            return Err(TransactionError::EmptyMint.into());
+        }
+        if mint.minted != policy.minted {
+            return Err(StateError::InvalidMintedSupply);
+        }
+        let value = mint.value.as_int();
+        if value > MAX_VALUE {
+            return Err(TransactionError::ValueOverflow.into());
+        }
+        let minted = policy
+            .minted
+            .checked_add(value)
+            .filter(|minted| *minted <= MAX_SUPPLY)
+            .ok_or(StateError::MintCapExceeded)?;
+        if policy.cap.is_some_and(|cap| minted > cap) {
+            return Err(StateError::MintCapExceeded);
+        }
+        Ok(policy)
+    }
+
+    /// Apply the transaction to the state. The fee (if any) is credited to `fee_collector`
+    /// as a new UTXO, inserted after the outputs of the transaction.
+    /// The transaction is checked in the same order as the MASM program checks it, so that an
+    /// invalid transaction gives the same error in both: each input in turn (it is in the
+    /// state, not spent before, unlocked and signed for), then the mint (see `check_mint`) and
+    /// the signature of the issuer, then the values (see `Transaction::verify`) and finally
+    /// the leaves of the outputs.
+    pub fn process_tx(
+        &mut self,
+        transaction: SignedTransaction,
+        fee_collector: Word,
+    ) -> Result<(), StateError> {
+        let tx = &transaction.transaction;
+        if tx.inputs.is_empty() && tx.mint.is_none() {
+            return Err(TransactionError::NoInputs.into());
+        }
+        transaction.check_signature_count()?;
+
+        // Check the inputs can be spent
+        let mut input_indices = Vec::with_capacity(tx.inputs.len());
+        let mut inputs = Vec::with_capacity(tx.inputs.len());
+        for (position, input) in tx.inputs.iter().enumerate() {
+            let index = self.leaf_index(input);
+            // Spending the same UTXO twice is the same as spending an unknown one
+            if input_indices.contains(&index) {
+                return Err(StateError::UnknownUtxoHash);
+            }
+            let utxo = self.get_utxo(input).ok_or(StateError::UnknownUtxoHash)?;
+            if self.is_nullified(index) {
+                return Err(StateError::AlreadySpent);
+            }
+            transaction.verify_input(position, utxo, self.height)?;
+            input_indices.push(index);
+            inputs.push(utxo.clone());
+        }
+
+        // Check the mint against the policy of its asset
+        let policy = match &tx.mint {
+            Some(mint) => {
+                let policy = self.check_mint(mint)?.clone();
+                transaction.verify_issuer(policy.issuer)?;
+                Some(policy)
+            }
+            None => None,
+        };
+
+        // Check the values
+        tx.verify(&inputs)?;
+
+        // Check the outputs fit in the tree (the leaves of the inputs hold their nullifiers
+        // once they are spent, so they cannot take outputs either)
+        let outputs = tx.output_utxos(fee_collector);
+        let mut output_indices = Vec::with_capacity(outputs.len());
+        for u in outputs.iter() {
+            let index = self.leaf_index(&u.hash());
+            if self.is_occupied(index) || output_indices.contains(&index) {
+                return Err(StateError::LeafOccupied);
+            }
+            output_indices.push(index);
+        }
+
+        // Remove spent UTXOs and record their nullifiers
+        for (index, input) in input_indices.into_iter().zip(tx.inputs.iter()) {
+            self.utxos.remove(&index);
+            // Safety: unwraps are safe because index came from the tree itself.
+            self.tree.update_leaf(index, Word::default()).unwrap();
+            self.nullifiers.update_leaf(index, *input).unwrap();
+        }
+
+        // Record the minted value in the policy
+        if let (Some(mut policy), Some(mint)) = (policy, &tx.mint) {
+            policy.minted += mint.value.as_int();
+            let index = self.leaf_index(&MintPolicy::key(policy.asset_id));
+            // Safety: unwrap is safe because the index is masked to the depth of the tree.
+            self.tree.update_leaf(index, policy.hash()).unwrap();
+            self.policies.insert(index, policy);
+        }
+
+        // Insert output UTXOs (and the fee)
+        for u in outputs {
+            self.insert(u)?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply all the transactions of the batch in order (with fees credited to `fee_collector`).
+    /// If any of them fails the state is left unchanged.
+    pub fn process_batch(&mut self, batch: Batch, fee_collector: Word) -> Result<(), BatchError> {
+        if batch.transactions.is_empty() {
+            return Err(BatchError::Empty);
+        }
+        let mut new_state = self.clone();
+        for (index, transaction) in batch.transactions.into_iter().enumerate() {
+            new_state
+                .process_tx(transaction, fee_collector)
+                .map_err(|error| BatchError::InvalidTransaction { index, error })?;
+        }
+        *self = new_state;
+        Ok(())
+    }
+
+    pub fn get_root(&self) -> Word {
+        self.tree.root().into()
+    }
+
+    pub fn get_nullifier_root(&self) -> Word {
+        self.nullifiers.root().into()
+    }
+
+    /// Whether a UTXO stored at the leaf `index` was spent before.
+    fn is_nullified(&self, index: u64) -> bool {
+        // Safety: unwrap is safe because the index is masked to the depth of the tree.
+        self.nullifiers.get_leaf(index).unwrap() != Word::default()
+    }
+
+    pub fn insert(&mut self, utxo: Utxo) -> Result<(), StateError> {
+        if utxo.value.as_int() > MAX_VALUE {
+            return Err(StateError::ValueOverflow);
+        }
+        if utxo.unlock_height.is_some_and(|height| height > MAX_HEIGHT) {
+            return Err(StateError::HeightOverflow);
+        }
+        let h = utxo.hash();
+        let index = self.leaf_index(&h);
+        if self.is_occupied(index) {
+            return Err(StateError::LeafOccupied);
+        }
+        // Safety: unwrap is safe because the index is masked to the depth of the tree.
+        self.tree.update_leaf(index, h).unwrap();
+        self.utxos.insert(index, utxo);
+        Ok(())
+    }
+
+    /// Register the minting policy of an asset, which can only have one.
+    pub fn insert_policy(&mut self, policy: MintPolicy) -> Result<(), StateError> {
+        if policy.minted > MAX_SUPPLY || policy.cap.is_some_and(|cap| cap > MAX_SUPPLY) {
+            return Err(StateError::ValueOverflow);
+        }
+        if policy.cap.is_some_and(|cap| policy.minted > cap) {
+            return Err(StateError::MintCapExceeded);
+        }
+        let index = self.leaf_index(&MintPolicy::key(policy.asset_id));
+        if self.is_occupied(index) {
+            return Err(StateError::LeafOccupied);
+        }
+        // Safety: unwrap is safe because the index is masked to the depth of the tree.
+        self.tree.update_leaf(index, policy.hash()).unwrap();
+        self.policies.insert(index, policy);
+        Ok(())
+    }
+
+    /// Whether the leaf `index` of the tree holds a UTXO or a minting policy, or holds a
+    /// nullifier in the nullifier tree (a UTXO stored there could never be spent).
+    pub fn is_occupied(&self, index: u64) -> bool {
+        self.utxos.contains_key(&index)
+            || self.policies.contains_key(&index)
+            || self.is_nullified(index)
+    }
+}
+
+/// Mask selecting the lowest `tree_depth` bits of a `u64`.
+pub fn leaf_index_mask(tree_depth: u8) -> u64 {
+    u64::MAX >> (64 - u32::from(tree_depth))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransactionError {
+    NoInputs,
+    InvalidInputHash,
+    ExcessiveOutput,
+    /// Part of the input value is neither spent by the outputs nor paid as a fee.
+    UnspentInput,
+    MissingSignature,
+    InvalidSignature,
+    /// An input cannot be spent before its unlock height.
+    LockedInput,
+    /// The multisig key set given for an input does not match its owner.
+    InvalidMultisig,
+    /// A mint must create some value.
+    EmptyMint,
+    /// A value is above `MAX_VALUE`.
+    ValueOverflow,
+    /// An unlock height is above `MAX_HEIGHT`.
+    HeightOverflow,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The leaf a new UTXO (or minting policy) would be stored at already holds a UTXO or a
+    /// minting policy (for example because the same UTXO already exists in the state), or a
+    /// UTXO stored at the leaf was spent before.
+    LeafOccupied,
+    InvalidTreeDepth,
+    UnknownUtxoHash,
+    /// A UTXO stored at the same leaf as the input was already spent (usually the input itself).
+    AlreadySpent,
+    InvalidNullifier,
+    /// The state cannot go back to a lower height.
+    HeightDecreased,
+    /// There is no minting policy for the minted asset.
+    UnknownAsset,
+    /// The mint was made for a different minted total than the one of the asset
+    /// (for example because it was already applied).
+    InvalidMintedSupply,
+    /// The mint would take the minted total of the asset above its cap (or `MAX_SUPPLY`), or
+    /// a minting policy has already minted more than its cap.
+    MintCapExceeded,
+    /// The value of a UTXO is above `MAX_VALUE`, or the cap or minted total of a minting
+    /// policy is above `MAX_SUPPLY`.
+    ValueOverflow,
+    /// The height of the state or the unlock height of a UTXO is above `MAX_HEIGHT`.
+    HeightOverflow,
+    InvalidTransaction(TransactionError),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<TransactionError> for StateError {
+    fn from(value: TransactionError) -> Self {
+        Self::InvalidTransaction(value)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BatchError {
+    Empty,
+    /// The transaction at position `index` in the batch could not be applied.
+    InvalidTransaction {
+        index: usize,
+        error: StateError,
+    },
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedKey {
+    pub pair: HexString,
+    pub owner: HexString,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedUtxo {
+    pub owner: HexString,
+    /// The native asset if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_id: Option<HexString>,
+    pub value: HexString,
+    pub serial: HexString,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unlock_height: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedOutput {
+    pub owner: HexString,
+    /// The native asset if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_id: Option<HexString>,
+    pub value: HexString,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unlock_height: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedMint {
+    pub asset_id: HexString,
+    pub value: HexString,
+    pub minted: u64,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedMintPolicy {
+    pub asset_id: HexString,
+    pub issuer: HexString,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cap: Option<u64>,
+    #[serde(default)]
+    pub minted: u64,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedState {
+    pub tree_depth: u8,
+    pub utxos: Vec<Utxo>,
+    /// Hashes of the spent UTXOs.
+    #[serde(default)]
+    pub nullifiers: Vec<HexString>,
+    #[serde(default)]
+    pub height: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<MintPolicy>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedTransaction {
+    pub inputs: Vec<HexString>,
+    pub outputs: Vec<SerializedOutput>,
+    pub fee: HexString,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint: Option<Mint>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedMultisig {
+    pub threshold: usize,
+    pub keys: Vec<HexString>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedKeySignature {
+    pub key_index: usize,
+    pub signature: HexString,
+}
+
+/// A single signature is just the hex string of the signature.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum SerializedInputSignature {
+    Single(HexString),
+    Multisig {
+        owner: Multisig,
+        signatures: Vec<SerializedKeySignature>,
+    },
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct SerializedSignedTransaction {
+    pub transaction: SerializedTransaction,
+    /// One signature per input (and one for the mint), see `SignedTransaction::signatures`.
+    pub signatures: Vec<SerializedInputSignature>,
+}
+
+impl TryFrom<SerializedKey> for Key {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SerializedKey) -> Result<Self, Self::Error> {
+        let owner = value.owner.try_into()?;
+        let pair = KeyPair::read_from_bytes(&value.pair.bytes)
+            .map_err(|e| anyhow::Error::msg(format!("{e:?}")))?;
+        Ok(Self { pair, owner })
+    }
+}
+
+impl From<Key> for SerializedKey {
+    fn from(value: Key) -> Self {
+        let owner = value.owner.into();
+        let pair_bytes = {
+            let mut buf = Vec::new();
+            value.pair.write_into(&mut buf);
+            buf
+        };
+        Self {
+            pair: HexString { bytes: pair_bytes },
+            owner,
+        }
+    }
+}
+
+impl TryFrom<SerializedUtxo> for Utxo {
+    type Error = anyhow::Error;
+
+    fn try_from(utxo: SerializedUtxo) -> Result<Self, Self::Error> {
+        let owner = utxo.owner.try_into()?;
+        let asset_id = asset_id_from(utxo.asset_id)?;
+        let value = utxo.value.try_into()?;
+        let serial = utxo.serial.try_into()?;
+        Ok(Self {
+            owner,
+            asset_id,
+            value,
+            serial,
+            unlock_height: unlock_height_from(utxo.unlock_height)?,
+        })
+    }
+}
+
+impl From<Utxo> for SerializedUtxo {
+    fn from(value: Utxo) -> Self {
+        let owner = value.owner.into();
+        let serial = value.serial.into();
+        let value_bytes = {
+            let mut buf = Vec::new();
+            value.value.write_into(&mut buf);
+            buf
+        };
+        Self {
+            owner,
+            asset_id: asset_id_into(value.asset_id),
+            value: HexString { bytes: value_bytes },
+            serial,
+            unlock_height: value.unlock_height,
+        }
+    }
+}
+
+impl TryFrom<SerializedOutput> for Output {
+    type Error = anyhow::Error;
+
+    fn try_from(output: SerializedOutput) -> Result<Self, Self::Error> {
+        let owner = output.owner.try_into()?;
+        let asset_id = asset_id_from(output.asset_id)?;
+        let value = output.value.try_into()?;
+        Ok(Self {
+            owner,
+            asset_id,
+            value,
+            unlock_height: unlock_height_from(output.unlock_height)?,
+        })
+    }
+}
+
+impl From<Output> for SerializedOutput {
+    fn from(value: Output) -> Self {
+        let owner = value.owner.into();
+        let value_bytes = {
+            let mut buf = Vec::new();
+            value.value.write_into(&mut buf);
+            buf
+        };
+        Self {
+            owner,
+            asset_id: asset_id_into(value.asset_id),
+            value: HexString { bytes: value_bytes },
+            unlock_height: value.unlock_height,
+        }
+    }
+}
+
+/// Asset ids are left out of the JSON formats for the native asset.
+fn asset_id_from(asset_id: Option<HexString>) -> anyhow::Result<Word> {
+    asset_id.map_or(Ok(NATIVE_ASSET), Word::try_from)
+}
+
+fn asset_id_into(asset_id: Word) -> Option<HexString> {
+    (asset_id != NATIVE_ASSET).then(|| asset_id.into())
+}
+
+fn unlock_height_from(unlock_height: Option<u64>) -> anyhow::Result<Option<u64>> {
+    if unlock_height.is_some_and(|height| height > MAX_HEIGHT) {
+        anyhow::bail!("Unlock height is above {MAX_HEIGHT}");
+    }
+    Ok(unlock_height)
+}
+
+impl TryFrom<SerializedMint> for Mint {
+    type Error = anyhow::Error;
+
+    fn try_from(mint: SerializedMint) -> Result<Self, Self::Error> {
+        Ok(Self {
+            asset_id: mint.asset_id.try_into()?,
+            value: mint.value.try_into()?,
+            minted: mint.minted,
+        })
+    }
+}
+
+impl From<Mint> for SerializedMint {
+    fn from(value: Mint) -> Self {
+        Self {
+            asset_id: value.asset_id.into(),
+            value: value.value.into(),
+            minted: value.minted,
+        }
+    }
+}
+
+impl TryFrom<SerializedMintPolicy> for MintPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(policy: SerializedMintPolicy) -> Result<Self, Self::Error> {
+        Ok(Self {
+            asset_id: policy.asset_id.try_into()?,
+            issuer: policy.issuer.try_into()?,
+            cap: policy.cap,
+            minted: policy.minted,
+        })
+    }
+}
+
+impl From<MintPolicy> for SerializedMintPolicy {
+    fn from(value: MintPolicy) -> Self {
+        Self {
+            asset_id: value.asset_id.into(),
+            issuer: value.issuer.into(),
+            cap: value.cap,
+            minted: value.minted,
+        }
+    }
+}
+
+impl TryFrom<SerializedState> for State {
+    type Error = StateError;
+
+    fn try_from(value: SerializedState) -> Result<Self, Self::Error> {
+        let mut state = Self::empty(value.tree_depth)?;
+        state.set_height(value.height)?;
+        for utxo in value.utxos {
+            state.insert(utxo)?;
+        }
+        for policy in value.policies {
+            state.insert_policy(policy)?;
+        }
+        for nullifier in value.nullifiers {
+            let nullifier: Word = nullifier
+                .try_into()
+                .map_err(|_| StateError::InvalidNullifier)?;
+            let index = state.leaf_index(&nullifier);
+            if state.is_nullified(index) {
+                return Err(StateError::AlreadySpent);
+            }
+            // A UTXO cannot be stored at a spent leaf (see `State::insert`)
+            if state.is_occupied(index) {
+                return Err(StateError::LeafOccupied);
+            }
+            // Safety: unwrap is safe because the index is masked to the depth of the tree.
+            state.nullifiers.update_leaf(index, nullifier).unwrap();
+        }
+        Ok(state)
+    }
+}
+
+impl From<State> for SerializedState {
+    fn from(value: State) -> Self {
+        let nullifiers = value
+            .nullifiers
+            .leaves()
+            .map(|(_, nullifier)| (*nullifier).into())
+            .collect();
+        Self {
+            tree_depth: value.tree_depth(),
+            utxos: value.utxos.into_values().collect(),
+            nullifiers,
+            height: value.height,
+            policies: value.policies.into_values().collect(),
+        }
+    }
+}
+
+impl TryFrom<SerializedTransaction> for Transaction {
+    type Error = anyhow::Error;
+
+    fn try_from(tx: SerializedTransaction) -> Result<Self, Self::Error> {
+        let inputs: anyhow::Result<Vec<Word>> =
+            tx.inputs.into_iter().map(|hash| hash.try_into()).collect();
+        let outputs: anyhow::Result<Vec<Output>> = tx
+            .outputs
+            .into_iter()
+            .map(|output| output.try_into())
+            .collect();
+        let fee = tx.fee.try_into()?;
+        Ok(Self {
+            inputs: inputs?,
+            outputs: outputs?,
+            fee,
+            mint: tx.mint,
+        })
+    }
+}
+
+impl From<Transaction> for SerializedTransaction {
+    fn from(value: Transaction) -> Self {
+        let inputs = value.inputs.into_iter().map(Into::into).collect();
+        let outputs = value.outputs.into_iter().map(Into::into).collect();
+        let fee = value.fee.into();
+        Self {
+            inputs,
+            outputs,
+            fee,
+            mint: value.mint,
+        }
+    }
+}
+
+impl TryFrom<SerializedMultisig> for Multisig {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SerializedMultisig) -> Result<Self, Self::Error> {
+        let keys = value
+            .keys
+            .into_iter()
+            .map(|key| key.try_into())
+            .collect::<anyhow::Result<Vec<Word>>>()?;
+        let multisig = Self::new(value.threshold, keys.clone())?;
+        // Sorting the keys would change the owner of the key set
+        if multisig.keys != keys {
+            anyhow::bail!("The keys must be in increasing order of their first element");
+        }
+        Ok(multisig)
+    }
+}
+
+impl From<Multisig> for SerializedMultisig {
+    fn from(value: Multisig) -> Self {
+        Self {
+            threshold: value.threshold,
+            keys: value.keys.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+fn read_signature(signature: &HexString) -> anyhow::Result<Signature> {
+    Signature::read_from_bytes(&signature.bytes)
+        .map_err(|e| anyhow::Error::msg(format!("Failed to deserialize signature: {e:?}")))
+}
+
+impl TryFrom<SerializedInputSignature> for InputSignature {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SerializedInputSignature) -> Result<Self, Self::Error> {
+        match value {
+            SerializedInputSignature::Single(signature) => {
+                Ok(Self::Single(read_signature(&signature)?))
+            }
+            SerializedInputSignature::Multisig { owner, signatures } => {
+                let signatures = signatures
+                    .iter()
+                    .map(|s| Ok((s.key_index, read_signature(&s.signature)?)))
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(Self::Multisig { owner, signatures })
+            }
+        }
+    }
+}
+
+impl From<InputSignature> for SerializedInputSignature {
+    fn from(value: InputSignature) -> Self {
+        match value {
+            InputSignature::Single(signature) => Self::Single(HexString {
+                bytes: signature.to_bytes(),
+            }),
+            InputSignature::Multisig { owner, signatures } => Self::Multisig {
+                owner,
+                signatures: signatures
+                    .into_iter()
+                    .map(|(key_index, signature)| SerializedKeySignature {
+                        key_index,
+                        signature: HexString {
+                            bytes: signature.to_bytes(),
+                        },
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl TryFrom<SerializedSignedTransaction> for SignedTransaction {
+    type Error = anyhow::Error;
+
+    fn try_from(value: SerializedSignedTransaction) -> Result<Self, Self::Error> {
+        let transaction = value.transaction.try_into()?;
+        let signatures = value
+            .signatures
+            .into_iter()
+            .map(InputSignature::try_from)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            transaction,
+            signatures,
+        })
+    }
+}
+
+impl From<SignedTransaction> for SerializedSignedTransaction {
+    fn from(value: SignedTransaction) -> Self {
+        let transaction = value.transaction.into();
+        let signatures = value.signatures.into_iter().map(Into::into).collect();
+        Self {
+            transaction,
+            signatures,
+        }
+    }
+}
+
+#[test]
+fn test_signed_transaction_serialization() {
+    let key = KeyPair::new().unwrap();
+    let transaction = Transaction {
+        inputs: vec![Word::default(), Word::default()],
+        outputs: Vec::new(),
+        fee: Felt::new(1),
+        mint: None,
+    };
+    let signed_tx = SignedTransaction::new(transaction, &[key, key]).unwrap();
+    let serialized: SerializedSignedTransaction = signed_tx.into();
+    let _: SignedTransaction = serialized.try_into().unwrap();
+}
+
+#[test]
+fn test_state_serialization() {
+    let key = KeyPair::new().unwrap();
+    let utxo = Utxo {
+        owner: key.public_key().into(),
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state.insert(utxo.clone()).unwrap();
+    assert!(matches!(state.insert(utxo), Err(StateError::LeafOccupied)));
+
+    let serialized = serde_json::to_string(&state).unwrap();
+    let deserialized: State = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(deserialized.get_root(), state.get_root());
+}
+
+#[test]
+fn test_unlock_height() {
+    let key = Key::random().unwrap();
+    let utxo = Utxo {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: Some(10),
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state.insert(utxo.clone()).unwrap();
+    state.set_height(9).unwrap();
+
+    let transaction = Transaction {
+        inputs: vec![utxo.hash()],
+        outputs: Vec::new(),
+        fee: Felt::new(100),
+        mint: None,
+    };
+    let signed_tx = SignedTransaction::new(transaction, &[key.pair]).unwrap();
+    assert!(matches!(
+        state.process_tx(signed_tx.clone(), Word::default()),
+        Err(StateError::InvalidTransaction(
+            TransactionError::LockedInput
+        ))
+    ));
+
+    state.set_height(10).unwrap();
+    assert!(matches!(
+        state.set_height(9),
+        Err(StateError::HeightDecreased)
+    ));
+    assert!(matches!(
+        state.set_height(MAX_HEIGHT + 1),
+        Err(StateError::HeightOverflow)
+    ));
+
+    // Heights are bounded so that they compare the same as field elements
+    let output = Output {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        unlock_height: Some(MAX_HEIGHT + 1),
+    };
+    let transaction = Transaction {
+        outputs: vec![output.clone()],
+        fee: ZERO,
+        ..signed_tx.transaction.clone()
+    };
+    assert!(matches!(
+        transaction.verify(&[utxo.clone()]),
+        Err(TransactionError::HeightOverflow)
+    ));
+    let serialized = serde_json::to_string(&output).unwrap();
+    assert!(serde_json::from_str::<Output>(&serialized).is_err());
+    let locked_utxo = Utxo {
+        unlock_height: Some(MAX_HEIGHT + 1),
+        ..utxo
+    };
+    assert!(matches!(
+        State::empty(State::DEFAULT_TREE_DEPTH)
+            .unwrap()
+            .insert(locked_utxo),
+        Err(StateError::HeightOverflow)
+    ));
+
+    state.process_tx(signed_tx, Word::default()).unwrap();
+}
+
+#[test]
+fn test_multisig() {
+    let keys = [
+        Key::random().unwrap(),
+        Key::random().unwrap(),
+        Key::random().unwrap(),
+    ];
+    let multisig = Multisig::new(2, keys.iter().map(|key| key.owner).collect()).unwrap();
+    assert!(Multisig::new(4, multisig.keys.clone()).is_err());
+    // A single key cannot meet a 2-of-2 threshold by being given twice
+    assert!(Multisig::new(2, vec![keys[0].owner, keys[0].owner]).is_err());
+    // Nor by a key set built without `Multisig::new`
+    let duplicate = Multisig {
+        threshold: 2,
+        keys: vec![keys[0].owner, keys[0].owner, keys[1].owner],
+    };
+    assert!(!duplicate.has_distinct_keys());
+    assert!(multisig.has_distinct_keys());
+    let utxo = Utxo {
+        owner: multisig.owner(),
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let transaction = Transaction {
+        inputs: vec![utxo.hash()],
+        outputs: Vec::new(),
+        fee: Felt::new(100),
+        mint: None,
+    };
+    let sign = |keys: &[KeyPair]| {
+        let signer = Signer::Multisig {
+            owner: &multisig,
+            keys,
+        };
+        SignedTransaction::with_signers(transaction.clone(), &[signer]).unwrap()
+    };
+
+    let signed_tx = sign(&[keys[0].pair]);
+    assert!(matches!(
+        signed_tx.verify(&[utxo.clone()], None, 0),
+        Err(TransactionError::MissingSignature)
+    ));
+
+    let signed_tx = sign(&[keys[1].pair, keys[0].pair]);
+    signed_tx.verify(&[utxo.clone()], None, 0).unwrap();
+    let serialized = serde_json::to_string(&SerializedSignedTransaction::from(signed_tx)).unwrap();
+    let deserialized: SerializedSignedTransaction = serde_json::from_str(&serialized).unwrap();
+    let signed_tx = SignedTransaction::try_from(deserialized).unwrap();
+    signed_tx.verify(&[utxo.clone()], None, 0).unwrap();
+
+    // A single key cannot spend the multisig UTXO
+    let signed_tx = SignedTransaction::new(transaction.clone(), &[keys[0].pair]).unwrap();
+    assert!(matches!(
+        signed_tx.verify(&[utxo], None, 0),
+        Err(TransactionError::InvalidSignature)
+    ));
+}
+
+#[test]
+fn test_multiple_assets() {
+    let owner = [Felt::new(1), ZERO, ZERO, ZERO];
+    let asset_id = [Felt::new(7), Felt::new(8), Felt::new(9), Felt::new(10)];
+    let utxo = |asset_id, value| Utxo {
+        owner,
+        asset_id,
+        value: Felt::new(value),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let output = |asset_id, value| Output {
+        owner,
+        asset_id,
+        value: Felt::new(value),
+        unlock_height: None,
+    };
+    let inputs = [utxo(NATIVE_ASSET, 100), utxo(asset_id, 50)];
+    let transaction = Transaction {
+        inputs: inputs.iter().map(Utxo::hash).collect(),
+        outputs: vec![output(asset_id, 50), output(NATIVE_ASSET, 90)],
+        fee: Felt::new(10),
+        mint: None,
+    };
+    transaction.verify(&inputs).unwrap();
+
+    // Each asset is conserved on its own, even if the overall total matches
+    let transaction = Transaction {
+        outputs: vec![output(asset_id, 60), output(NATIVE_ASSET, 80)],
+        ..transaction
+    };
+    assert!(matches!(
+        transaction.verify(&inputs),
+        Err(TransactionError::UnspentInput)
+    ));
+
+    // The fee is paid in the native asset
+    let inputs = [utxo(asset_id, 50)];
+    let transaction = Transaction {
+        inputs: inputs.iter().map(Utxo::hash).collect(),
+        outputs: vec![output(asset_id, 40)],
+        fee: Felt::new(10),
+        mint: None,
+    };
+    assert!(matches!(
+        transaction.verify(&inputs),
+        Err(TransactionError::UnspentInput)
+    ));
+}
+
+#[test]
+fn test_mint() {
+    let issuer = Key::random().unwrap();
+    let asset_id = [Felt::new(7), Felt::new(8), Felt::new(9), Felt::new(10)];
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state
+        .insert_policy(MintPolicy::new(asset_id, issuer.owner, Some(100)))
+        .unwrap();
+    assert!(matches!(
+        state.insert_policy(MintPolicy::new(asset_id, issuer.owner, None)),
+        Err(StateError::LeafOccupied)
+    ));
+
+    let mint = |value: u64, minted: u64| Transaction {
+        inputs: Vec::new(),
+        outputs: vec![Output {
+            owner: issuer.owner,
+            asset_id,
+            value: Felt::new(value),
+            unlock_height: None,
+        }],
+        fee: Felt::new(0),
+        mint: Some(Mint {
+            asset_id,
+            value: Felt::new(value),
+            minted,
+        }),
+    };
+    let signed_tx = SignedTransaction::new(mint(60, 0), &[issuer.pair]).unwrap();
+    state
+        .process_tx(signed_tx.clone(), Word::default())
+        .unwrap();
+    assert_eq!(state.get_policy(&asset_id).unwrap().minted, 60);
+
+    // The same mint cannot be applied twice
+    assert!(matches!(
+        state.process_tx(signed_tx, Word::default()),
+        Err(StateError::InvalidMintedSupply)
+    ));
+    let signed_tx = SignedTransaction::new(mint(50, 60), &[issuer.pair]).unwrap();
+    assert!(matches!(
+        state.process_tx(signed_tx, Word::default()),
+        Err(StateError::MintCapExceeded)
+    ));
+    let other_key = Key::random().unwrap();
+    let signed_tx = SignedTransaction::new(mint(40, 60), &[other_key.pair]).unwrap();
+    assert!(matches!(
+        state.process_tx(signed_tx, Word::default()),
+        Err(StateError::InvalidTransaction(
+            TransactionError::InvalidSignature
+        ))
+    ));
+    let signed_tx = SignedTransaction::new(mint(0, 60), &[issuer.pair]).unwrap();
+    assert!(matches!(
+        state.process_tx(signed_tx, Word::default()),
+        Err(StateError::InvalidTransaction(TransactionError::EmptyMint))
+    ));
+    let mut transaction = mint(40, 0);
+    transaction.mint.as_mut().unwrap().asset_id = NATIVE_ASSET;
+    let signed_tx = SignedTransaction::new(transaction, &[issuer.pair]).unwrap();
+    assert!(matches!(
+        state.process_tx(signed_tx, Word::default()),
+        Err(StateError::UnknownAsset)
+    ));
+
+    let signed_tx = SignedTransaction::new(mint(MAX_VALUE + 1, 60), &[issuer.pair]).unwrap();
+    assert!(matches!(
+        state.process_tx(signed_tx, Word::default()),
+        Err(StateError::InvalidTransaction(
+            TransactionError::ValueOverflow
+        ))
+    ));
+
+    // The policy is committed to by the state root and kept by the serialized state
+    let signed_tx = SignedTransaction::new(mint(40, 60), &[issuer.pair]).unwrap();
+    let root = state.get_root();
+    state.process_tx(signed_tx, Word::default()).unwrap();
+    assert_ne!(state.get_root(), root);
+    let serialized = serde_json::to_string(&state).unwrap();
+    let deserialized: State = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(deserialized.get_root(), state.get_root());
+    assert_eq!(deserialized.get_policy(&asset_id).unwrap().minted, 100);
+
+    // Minted totals stay far below the field modulus, even without a cap
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    let policy = MintPolicy {
+        minted: MAX_SUPPLY - 10,
+        ..MintPolicy::new(asset_id, issuer.owner, None)
+    };
+    state.insert_policy(policy).unwrap();
+    let mint = |value| Mint {
+        asset_id,
+        value: Felt::new(value),
+        minted: MAX_SUPPLY - 10,
+    };
+    assert!(state.check_mint(&mint(10)).is_ok());
+    assert!(matches!(
+        state.check_mint(&mint(11)),
+        Err(StateError::MintCapExceeded)
+    ));
+    let policy = MintPolicy::new(NATIVE_ASSET, issuer.owner, Some(MAX_SUPPLY + 1));
+    assert!(matches!(
+        state.insert_policy(policy),
+        Err(StateError::ValueOverflow)
+    ));
+    let policy = MintPolicy {
+        minted: MAX_SUPPLY + 1,
+        ..MintPolicy::new(NATIVE_ASSET, issuer.owner, None)
+    };
+    assert!(matches!(
+        state.insert_policy(policy),
+        Err(StateError::ValueOverflow)
+    ));
+
+    // A policy cannot have minted more than its cap, nor be loaded with such a total
+    let policy = MintPolicy {
+        minted: 101,
+        ..MintPolicy::new(NATIVE_ASSET, issuer.owner, Some(100))
+    };
+    assert!(matches!(
+        state.insert_policy(policy.clone()),
+        Err(StateError::MintCapExceeded)
+    ));
+    let serialized = SerializedState {
+        tree_depth: State::DEFAULT_TREE_DEPTH,
+        utxos: vec![],
+        nullifiers: vec![],
+        height: 0,
+        policies: vec![policy],
+    };
+    assert!(matches!(
+        State::try_from(serialized),
+        Err(StateError::MintCapExceeded)
+    ));
+}
+
+#[test]
+fn test_value_overflow() {
+    let owner = [Felt::new(1), ZERO, ZERO, ZERO];
+    let input = Utxo {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let output = |value| Output {
+        owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(value),
+        unlock_height: None,
+    };
+    // The outputs add up to the input value modulo the field modulus
+    let transaction = Transaction {
+        inputs: vec![input.hash()],
+        outputs: vec![output(Felt::MODULUS - 50), output(150)],
+        fee: ZERO,
+        mint: None,
+    };
+    assert!(matches!(
+        transaction.verify(&[input.clone()]),
+        Err(TransactionError::ValueOverflow)
+    ));
+    let transaction = Transaction {
+        outputs: vec![output(MAX_VALUE + 1)],
+        ..transaction
+    };
+    assert!(matches!(
+        transaction.verify(&[input.clone()]),
+        Err(TransactionError::ValueOverflow)
+    ));
+
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    let utxo = Utxo {
+        value: Felt::new(MAX_VALUE + 1),
+        ..input
+    };
+    assert!(matches!(state.insert(utxo), Err(StateError::ValueOverflow)));
+}
+
+#[test]
+fn test_nullified_leaf() {
+    let key = Key::random().unwrap();
+    let utxo = Utxo {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(100),
+        serial: Word::default(),
+        unlock_height: None,
+    };
+    let mut state = State::empty(2).unwrap();
+    state.insert(utxo.clone()).unwrap();
+    let index = state.leaf_index(&utxo.hash());
+    // Transactions spending the UTXO into one output, the first one whose output is stored
+    // at (or not at) the leaf of the UTXO
+    let spend = |on_leaf: bool| {
+        let transaction = (1..)
+            .map(|unlock_height| Transaction {
+                inputs: vec![utxo.hash()],
+                outputs: vec![Output {
+                    owner: key.owner,
+                    asset_id: NATIVE_ASSET,
+                    value: Felt::new(100),
+                    unlock_height: Some(unlock_height),
+                }],
+                fee: ZERO,
+                mint: None,
+            })
+            .find(|tx| {
+                let output = &tx.output_utxos(Word::default())[0];
+                (state.leaf_index(&output.hash()) == index) == on_leaf
+            })
+            .unwrap();
+        SignedTransaction::new(transaction, &[key.pair]).unwrap()
+    };
+
+    // The leaf of the input holds its nullifier once it is spent, so it cannot take an output
+    assert!(matches!(
+        state.clone().process_tx(spend(true), Word::default()),
+        Err(StateError::LeafOccupied)
+    ));
+
+    // Nor can a UTXO be inserted at the leaf of a spent UTXO
+    let signed_tx = spend(false);
+    state.process_tx(signed_tx, Word::default()).unwrap();
+    let utxo = (0..)
+        .map(|serial| Utxo {
+            serial: [Felt::new(serial), ZERO, ZERO, ZERO],
+            ..utxo.clone()
+        })
+        .find(|utxo| state.leaf_index(&utxo.hash()) == index)
+        .unwrap();
+    assert!(matches!(state.insert(utxo), Err(StateError::LeafOccupied)));
+}
@@ -0,0 +1,35 @@
+use crate::{
+    prove::{self, ProveOutput},
+    utxo::{Batch, State},
+};
+use miden::{Program, ProgramInfo, Word};
+
+/// Verify a proof generated by `prove::prove` for the given batch against the given
+/// state (with fees credited to `fee_collector`). On success the new state root and
+/// nullifier root (taken from the proof outputs) are returned.
+pub fn verify(
+    program: &Program,
+    state: &State,
+    batch: &Batch,
+    fee_collector: Word,
+    output: ProveOutput,
+) -> anyhow::Result<(Word, Word)> {
+    let program_hash: [u8; 32] = program.hash().into();
+    if program_hash != output.program_hash {
+        anyhow::bail!("Proof was generated for a different program");
+    }
+
+    let stack_inputs = prove::prepare_stack_inputs(state, batch, fee_collector);
+
+    let new_state_root = output.state_root();
+    let new_nullifier_root = output.nullifier_root();
+    miden::verify(
+        ProgramInfo::from(program.clone()),
+        stack_inputs,
+        output.stack_outputs,
+        output.proof,
+    )
+    .map_err(|e| anyhow::Error::msg(format!("Proof verification failed: {e:?}")))?;
+
+    Ok((new_state_root, new_nullifier_root))
+}
@@ -0,0 +1,345 @@
+//! Wallet view of the state: the UTXOs owned by the keys (and multisig key sets) of a key
+//! directory, and their balances.
+//!
+//! Key files are named after their owner (`<owner>.json` with the owner as hex, without the
+//! `0x` prefix) and hold either a `Key` (possibly encrypted, see `EncryptedKey`) or a
+//! `Multisig` key set. Other files are ignored.
+
+use crate::{
+    keystore::EncryptedKey,
+    utils::{self, HexString},
+    utxo::{Key, Multisig, Output, State, Transaction, Utxo, NATIVE_ASSET},
+};
+use anyhow::Context;
+use miden_crypto::{Felt, StarkField, Word};
+use std::{fmt, path::Path, str::FromStr};
+
+/// Contents of a key file.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum KeyFile {
+    Key(Key),
+    EncryptedKey(EncryptedKey),
+    Multisig(Multisig),
+}
+
+impl KeyFile {
+    pub fn owner(&self) -> Word {
+        match self {
+            Self::Key(key) => key.owner,
+            Self::EncryptedKey(key) => key.owner,
+            Self::Multisig(multisig) => multisig.owner(),
+        }
+    }
+}
+
+/// Owners of the key files of a directory.
+#[derive(Debug, Default)]
+pub struct Wallet {
+    /// Owners in the order of their file names, with whether they are multisig owners.
+    pub owners: Vec<(Word, bool)>,
+}
+
+/// Value an owner holds in one asset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Balance {
+    pub asset_id: Word,
+    pub value: u64,
+    /// Part of the value in UTXOs which cannot be spent yet at the height of the state.
+    pub locked: u64,
+}
+
+impl Wallet {
+    /// Load the owners of all the key files in the directory.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).context("Failed to read key directory")? {
+            let path = entry?.path();
+            let is_key_file = path.extension().map(|ext| ext == "json").unwrap_or(false)
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| HexString::from_str(stem).ok())
+                    .is_some_and(|owner| owner.bytes.len() == 32);
+            if is_key_file {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut owners = Vec::with_capacity(paths.len());
+        for path in paths {
+            let file: KeyFile = utils::read_json_file(&path)
+                .with_context(|| format!("Failed to read key file {path:?}"))?;
+            owners.push((file.owner(), matches!(file, KeyFile::Multisig(_))));
+        }
+        Ok(Self { owners })
+    }
+
+    pub fn contains(&self, owner: &Word) -> bool {
+        self.owners.iter().any(|(o, _)| o == owner)
+    }
+
+    /// UTXOs of the state owned by the wallet, with their hashes.
+    pub fn utxos<'a>(&'a self, state: &'a State) -> impl Iterator<Item = (Word, &'a Utxo)> {
+        state
+            .utxos
+            .values()
+            .filter(|utxo| self.contains(&utxo.owner))
+            .map(|utxo| (utxo.hash(), utxo))
+    }
+
+    /// Balance of each asset held by the owner in the state, in the order the assets are
+    /// first found in the state.
+    pub fn balances(state: &State, owner: &Word) -> Vec<Balance> {
+        let mut balances: Vec<Balance> = Vec::new();
+        for utxo in state.utxos.values().filter(|utxo| &utxo.owner == owner) {
+            let index = match balances.iter().position(|b| b.asset_id == utxo.asset_id) {
+                Some(index) => index,
+                None => {
+                    balances.push(Balance {
+                        asset_id: utxo.asset_id,
+                        value: 0,
+                        locked: 0,
+                    });
+                    balances.len() - 1
+                }
+            };
+            let value = utxo.value.as_int();
+            balances[index].value += value;
+            if utxo.is_locked(state.height) {
+                balances[index].locked += value;
+            }
+        }
+        balances
+    }
+}
+
+/// Select UTXOs of the owner holding at least `target` of the asset, among the ones which
+/// can be spent at the height of the state: the smallest UTXO holding enough value if there
+/// is one, and the largest UTXOs until their total is enough otherwise.
+pub fn select_inputs<'a>(
+    state: &'a State,
+    owner: &Word,
+    asset_id: &Word,
+    target: u64,
+) -> Result<Vec<&'a Utxo>, WalletError> {
+    let mut candidates: Vec<&Utxo> = state
+        .utxos
+        .values()
+        .filter(|utxo| &utxo.owner == owner && &utxo.asset_id == asset_id)
+        .filter(|utxo| !utxo.is_locked(state.height))
+        .collect();
+    candidates.sort_by_key(|utxo| utxo.value.as_int());
+    if let Some(utxo) = candidates.iter().find(|utxo| utxo.value.as_int() >= target) {
+        return Ok(vec![*utxo]);
+    }
+
+    let mut selected = Vec::new();
+    let mut total = 0;
+    for utxo in candidates.into_iter().rev() {
+        total += utxo.value.as_int();
+        selected.push(utxo);
+        if total >= target {
+            return Ok(selected);
+        }
+    }
+    Err(WalletError::InsufficientFunds {
+        asset_id: *asset_id,
+        available: total,
+    })
+}
+
+/// Build a transaction sending `amount` of the asset from `from` to `to` and paying `fee`
+/// (in the native asset). The inputs are selected among the UTXOs of `from` (see
+/// `select_inputs`) and the rest of their value goes back to `from` as change outputs.
+pub fn transfer(
+    state: &State,
+    from: Word,
+    to: Word,
+    asset_id: Word,
+    amount: u64,
+    fee: u64,
+) -> Result<Transaction, WalletError> {
+    if amount == 0 {
+        return Err(WalletError::EmptyAmount);
+    }
+    let mut targets = vec![(asset_id, amount)];
+    if fee > 0 {
+        match targets.iter_mut().find(|(id, _)| id == &NATIVE_ASSET) {
+            Some((_, target)) => {
+                *target = target.checked_add(fee).ok_or(WalletError::ValueOverflow)?
+            }
+            None => targets.push((NATIVE_ASSET, fee)),
+        }
+    }
+
+    let mut inputs = Vec::new();
+    let mut outputs = vec![Output {
+        owner: to,
+        asset_id,
+        value: Felt::new(amount),
+        unlock_height: None,
+    }];
+    for (asset_id, target) in targets {
+        let selected = select_inputs(state, &from, &asset_id, target)?;
+        let total: u64 = selected.iter().map(|utxo| utxo.value.as_int()).sum();
+        if total > target {
+            outputs.push(Output {
+                owner: from,
+                asset_id,
+                value: Felt::new(total - target),
+                unlock_height: None,
+            });
+        }
+        inputs.extend(selected.into_iter().map(Utxo::hash));
+    }
+    Ok(Transaction {
+        inputs,
+        outputs,
+        fee: Felt::new(fee),
+        mint: None,
+    })
+}
+
+#[derive(Debug)]
+pub enum WalletError {
+    EmptyAmount,
+    /// The spendable UTXOs of the sender only hold `available` of the asset.
+    InsufficientFunds {
+        asset_id: Word,
+        available: u64,
+    },
+    /// The amount plus the fee does not fit in a `u64`.
+    ValueOverflow,
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for WalletError {}
+
+#[test]
+fn test_wallet() {
+    let key = Key::random().unwrap();
+    let other = Key::random().unwrap();
+    let asset_id = [Felt::new(7), Felt::new(8), Felt::new(9), Felt::new(10)];
+    let utxo = |owner, asset_id, value, unlock_height| Utxo {
+        owner,
+        asset_id,
+        value: Felt::new(value),
+        serial: [Felt::new(value), Felt::new(0), Felt::new(0), Felt::new(0)],
+        unlock_height,
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    state
+        .insert(utxo(key.owner, NATIVE_ASSET, 100, None))
+        .unwrap();
+    state
+        .insert(utxo(key.owner, NATIVE_ASSET, 20, Some(5)))
+        .unwrap();
+    state.insert(utxo(key.owner, asset_id, 30, None)).unwrap();
+    state
+        .insert(utxo(other.owner, NATIVE_ASSET, 40, None))
+        .unwrap();
+
+    let dir = std::env::temp_dir().join(format!("utxo-miden-wallet-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let key_path = dir.join(format!(
+        "{}.json",
+        hex::encode(HexString::from(key.owner).bytes)
+    ));
+    std::fs::write(&key_path, serde_json::to_string(&key).unwrap()).unwrap();
+    std::fs::write(dir.join("genesis.json"), "{}").unwrap();
+    let wallet = Wallet::load(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let wallet = wallet.unwrap();
+    assert_eq!(wallet.owners, vec![(key.owner, false)]);
+    assert_eq!(wallet.utxos(&state).count(), 3);
+
+    let mut balances = Wallet::balances(&state, &key.owner);
+    balances.sort_by_key(|balance| balance.asset_id != NATIVE_ASSET);
+    assert_eq!(
+        balances,
+        vec![
+            Balance {
+                asset_id: NATIVE_ASSET,
+                value: 120,
+                locked: 20,
+            },
+            Balance {
+                asset_id,
+                value: 30,
+                locked: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_transfer() {
+    use crate::utxo::{SignedTransaction, StateError, TransactionError};
+
+    let key = Key::random().unwrap();
+    let recipient = Key::random().unwrap();
+    let utxo = |value, unlock_height| Utxo {
+        owner: key.owner,
+        asset_id: NATIVE_ASSET,
+        value: Felt::new(value),
+        serial: [Felt::new(value), Felt::new(0), Felt::new(0), Felt::new(0)],
+        unlock_height,
+    };
+    let mut state = State::empty(State::DEFAULT_TREE_DEPTH).unwrap();
+    for (value, unlock_height) in [(100, None), (20, None), (30, None), (50, Some(5))] {
+        state.insert(utxo(value, unlock_height)).unwrap();
+    }
+
+    // The smallest UTXO holding enough value is spent, with the rest as change
+    let transaction = transfer(&state, key.owner, recipient.owner, NATIVE_ASSET, 25, 2).unwrap();
+    assert_eq!(transaction.inputs, vec![utxo(30, None).hash()]);
+    assert_eq!(transaction.outputs.len(), 2);
+    assert_eq!(transaction.outputs[1].owner, key.owner);
+    assert_eq!(transaction.outputs[1].value, Felt::new(3));
+    let signed_tx = SignedTransaction::new(transaction, &[key.pair]).unwrap();
+    state
+        .clone()
+        .process_tx(signed_tx, Word::default())
+        .unwrap();
+
+    // Otherwise the largest ones are spent (locked UTXOs cannot be)
+    let inputs = select_inputs(&state, &key.owner, &NATIVE_ASSET, 140).unwrap();
+    assert_eq!(inputs.len(), 3);
+    assert!(matches!(
+        transfer(&state, key.owner, recipient.owner, NATIVE_ASSET, 150, 1),
+        Err(WalletError::InsufficientFunds { available: 150, .. })
+    ));
+    assert!(matches!(
+        transfer(&state, key.owner, recipient.owner, NATIVE_ASSET, 0, 1),
+        Err(WalletError::EmptyAmount)
+    ));
+    assert!(matches!(
+        transfer(
+            &state,
+            key.owner,
+            recipient.owner,
+            NATIVE_ASSET,
+            u64::MAX,
+            1
+        ),
+        Err(WalletError::ValueOverflow)
+    ));
+
+    // Inputs are only signed for by their owner
+    let transaction = transfer(&state, key.owner, recipient.owner, NATIVE_ASSET, 10, 0).unwrap();
+    let signed_tx = SignedTransaction::new(transaction, &[recipient.pair]).unwrap();
+    assert!(matches!(
+        state.process_tx(signed_tx, Word::default()),
+        Err(StateError::InvalidTransaction(
+            TransactionError::InvalidSignature
+        ))
+    ));
+}